@@ -50,6 +50,12 @@ pub(crate) async fn get_block_headers(
   )
   .await;
 
+  crate::metrics::observe_outcall_cycles(cycles);
+  crate::metrics::observe_rpc("bitcoin_get_block_headers", res.is_ok());
+  if let Ok((response,)) = &res {
+    crate::metrics::observe_tip_height(response.tip_height);
+  }
+
   res
 }
 
@@ -1,11 +1,14 @@
+use self::backfill::Backfill;
 use self::entry::{Entry, RuneEntry};
 use self::lot::Lot;
 use super::Result;
 use crate::config::Config;
 use crate::index::entry::{
-  ChangeRecord, HeaderValue, OutPointValue, RuneBalances, RuneIdValue, TxidValue,
+  BlockRunesSummary, ChangeRecord, HeaderValue, OutPointValue, RuneBalances, RuneIdValue,
+  RuneTransaction, ScriptHashValue, SpentOutPoint, TxidValue,
 };
 use crate::logs::{CRITICAL, INFO};
+use crate::metrics::Metrics;
 use anyhow::anyhow;
 use bitcoin::{
   block::Header,
@@ -13,7 +16,7 @@ use bitcoin::{
   consensus::{self, Decodable, Encodable},
   hash_types::BlockHash,
   hashes::Hash,
-  Block, OutPoint, Transaction, Txid,
+  Block, OutPoint, Script, Transaction, Txid,
 };
 use ic_canister_log::log;
 use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
@@ -23,10 +26,14 @@ use ordinals::{
   Artifact, Edict, Etching, Height, Pile, Rune, RuneId, Runestone, SatPoint, SpacedRune, Terms,
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::sync::atomic::{self, AtomicBool};
+use std::ops::Bound;
+use std::sync::atomic::{self, AtomicBool, AtomicU64};
 
+pub mod backfill;
+pub mod certification;
 pub mod entry;
 mod lot;
 mod reorg;
@@ -34,6 +41,15 @@ pub mod updater;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
+/// Number of memories handed out by `MEMORY_MANAGER`, ids `0..MEMORY_COUNT`.
+pub const MEMORY_COUNT: u8 = 27;
+
+const WASM_PAGE_SIZE: u64 = 65536;
+
+/// Keys a prune removes per block. A backlog, e.g. after the retention was reduced, is worked
+/// off over the following blocks instead of in one message.
+const MAX_PRUNED_KEYS: usize = 2_000;
+
 thread_local! {
   static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
       RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
@@ -98,9 +114,127 @@ thread_local! {
           MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))),
       )
   );
+
+  static SCRIPT_HASH_TO_OUTPOINTS: RefCell<StableBTreeMap<(ScriptHashValue, OutPointValue), (), Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))),
+      )
+  );
+
+  static OUTPOINT_TO_SCRIPT_HASH: RefCell<StableBTreeMap<OutPointValue, ScriptHashValue, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))),
+      )
+  );
+
+  static RUNE_ID_TO_OUTPOINTS: RefCell<StableBTreeMap<(RuneIdValue, OutPointValue), u128, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))),
+      )
+  );
+
+  static RUNE_ID_TO_OUTPOINT_COUNT: RefCell<StableBTreeMap<RuneIdValue, u64, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))),
+      )
+  );
+
+  static NUMBER_TO_RUNE_ID: RefCell<StableBTreeMap<u64, RuneIdValue, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14))),
+      )
+  );
+
+  static TRANSACTION_ID_TO_RUNE_TRANSACTION: RefCell<StableBTreeMap<TxidValue, RuneTransaction, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15))),
+      )
+  );
+
+  static HEIGHT_TO_RUNE_TRANSACTIONS: RefCell<StableBTreeMap<(u32, TxidValue), (), Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16))),
+      )
+  );
+
+  static OUTPOINT_TO_SPENT_OUTPOINT: RefCell<StableBTreeMap<OutPointValue, SpentOutPoint, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17))),
+      )
+  );
+
+  // (mints, burned) of a rune before the block at the given height changed them
+  static RUNE_ID_HEIGHT_TO_SUPPLY: RefCell<StableBTreeMap<(RuneIdValue, u32), (u128, u128), Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18))),
+      )
+  );
+
+  static HEIGHT_TO_SPENT_OUTPOINTS: RefCell<StableBTreeMap<(u32, OutPointValue), (), Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))),
+      )
+  );
+
+  static HEIGHT_TO_BLOCK_RUNES_SUMMARY: RefCell<StableBTreeMap<u32, BlockRunesSummary, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20))),
+      )
+  );
+
+  // (bucket, key) -> value hash of the certified tree
+  static CERTIFIED_LEAVES: RefCell<StableBTreeMap<(u32, [u8; 32]), [u8; 32], Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21))),
+      )
+  );
+
+  // (level, index) -> hash of the certified tree, empty subtrees are not stored
+  static CERTIFIED_NODES: RefCell<StableBTreeMap<(u8, u32), [u8; 32], Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22))),
+      )
+  );
+
+  static METRICS: RefCell<StableCell<Metrics, Memory>> = RefCell::new(
+      StableCell::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23))),
+          Metrics::default()
+      ).unwrap()
+  );
+
+  // (script hash, rune id) -> balance of the rune summed over the outpoints locked by the script
+  static SCRIPT_HASH_TO_RUNE_TOTALS: RefCell<StableBTreeMap<(ScriptHashValue, RuneIdValue), u128, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24))),
+      )
+  );
+
+  static BACKFILL: RefCell<StableCell<Backfill, Memory>> = RefCell::new(
+      StableCell::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25))),
+          Backfill::default()
+      ).unwrap()
+  );
+
+  // height from which the address index covers every output, see `start_address_index`
+  static ADDRESS_INDEX_START: RefCell<StableCell<u32, Memory>> = RefCell::new(
+      StableCell::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26))),
+          ADDRESS_INDEX_START_UNSET
+      ).unwrap()
+  );
 }
 
+/// `ADDRESS_INDEX_START` of a deployment that has not run `start_address_index` yet.
+const ADDRESS_INDEX_START_UNSET: u32 = u32::MAX;
+
 static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+static INDEXING: AtomicBool = AtomicBool::new(false);
+// set when the loop stopped on an error it cannot recover from, until it is started again
+static HALTED: AtomicBool = AtomicBool::new(false);
+// generation of the index loop whose iteration is in flight, `0` if none
+static IN_FLIGHT: AtomicU64 = AtomicU64::new(0);
 
 pub fn shut_down() {
   SHUTTING_DOWN.store(true, atomic::Ordering::Relaxed);
@@ -114,6 +248,63 @@ pub fn is_shutting_down() -> bool {
   SHUTTING_DOWN.load(atomic::Ordering::Relaxed)
 }
 
+/// Whether the index timer loop is scheduled.
+pub fn is_indexing() -> bool {
+  INDEXING.load(atomic::Ordering::Relaxed)
+}
+
+pub(crate) fn set_indexing(indexing: bool) {
+  INDEXING.store(indexing, atomic::Ordering::Relaxed);
+}
+
+/// Whether the index loop stopped on an error it cannot recover from, such as a reorg deeper
+/// than the change records.
+pub fn is_halted() -> bool {
+  HALTED.load(atomic::Ordering::Relaxed)
+}
+
+pub(crate) fn set_halted(halted: bool) {
+  HALTED.store(halted, atomic::Ordering::Relaxed);
+}
+
+/// Whether an iteration of the index loop is between its first and last await.
+pub fn is_in_flight() -> bool {
+  IN_FLIGHT.load(atomic::Ordering::Relaxed) != 0
+}
+
+/// Marks an iteration of the loop `generation` in flight until the returned guard is dropped,
+/// which also happens when the iteration traps after an await.
+pub(crate) fn begin_iteration(generation: u64) -> InFlight {
+  IN_FLIGHT.store(generation, atomic::Ordering::Relaxed);
+  InFlight(generation)
+}
+
+pub(crate) struct InFlight(u64);
+
+impl Drop for InFlight {
+  fn drop(&mut self) {
+    // the iteration trapped and its changes since the last await were rolled back, the watchdog
+    // restarts the loop after a backoff
+    if ic_cdk::api::call::is_recovering_from_trap() {
+      let message = format!(
+        "index loop trapped at height {}, restarting after backoff",
+        next_block(mem_get_config().network).0
+      );
+      log!(CRITICAL, "{}", message);
+      crate::metrics::observe_error(message);
+      set_indexing(false);
+      updater::trapped();
+    }
+    // an iteration of a replaced loop must not clear the flag of its successor
+    let _ = IN_FLIGHT.compare_exchange(
+      self.0,
+      0,
+      atomic::Ordering::Relaxed,
+      atomic::Ordering::Relaxed,
+    );
+  }
+}
+
 pub fn mem_get_config() -> Config {
   CONFIG.with(|m| m.borrow().get().clone())
 }
@@ -124,6 +315,42 @@ pub fn mem_set_config(config: Config) -> Result<Config> {
     .map_err(|e| anyhow::anyhow!("Failed to set config: {:?}", e))
 }
 
+pub fn mem_get_backfill() -> Backfill {
+  BACKFILL.with(|m| m.borrow().get().clone())
+}
+
+pub(crate) fn mem_set_backfill(backfill: Backfill) {
+  BACKFILL
+    .with(|m| m.borrow_mut().set(backfill))
+    .expect("Failed to set backfill");
+}
+
+/// Height from which the address index covers every output. Outputs of earlier blocks that a
+/// deployment indexed before the index was introduced are missing from it until a re-index,
+/// since their scripts were never stored.
+pub fn mem_get_address_index_start() -> u32 {
+  ADDRESS_INDEX_START.with(|m| *m.borrow().get())
+}
+
+/// Records that the address index covers the blocks from the next one on, unless it was recorded
+/// before. Called on install, where that is every block, and after upgrades.
+pub fn start_address_index() {
+  if mem_get_address_index_start() == ADDRESS_INDEX_START_UNSET {
+    let height = mem_latest_block_height().map_or(0, |height| height + 1);
+    ADDRESS_INDEX_START
+      .with(|m| m.borrow_mut().set(height))
+      .expect("Failed to set address index start");
+  }
+}
+
+pub(crate) fn mem_get_metrics() -> Metrics {
+  METRICS.with(|m| m.borrow().get().clone())
+}
+
+pub(crate) fn mem_set_metrics(metrics: Metrics) {
+  METRICS.with(|m| m.borrow_mut().set(metrics).expect("Failed to set metrics"));
+}
+
 pub fn mem_latest_block() -> Option<(u32, BlockHash)> {
   HEIGHT_TO_BLOCK_HEADER.with(|m| {
     m.borrow()
@@ -250,17 +477,117 @@ pub fn mem_get_outpoint_to_rune_balances(outpoint_value: OutPointValue) -> Optio
   OUTPOINT_TO_RUNE_BALANCES.with(|m| m.borrow().get(&outpoint_value))
 }
 
+/// Also keeps the per-rune holder index in sync, so callers never touch it directly.
 pub fn mem_insert_outpoint_to_rune_balances(
   outpoint_value: OutPointValue,
   rune_balances: RuneBalances,
 ) {
-  OUTPOINT_TO_RUNE_BALANCES.with(|m| m.borrow_mut().insert(outpoint_value, rune_balances));
+  if let Some(previous) =
+    OUTPOINT_TO_RUNE_BALANCES.with(|m| m.borrow_mut().insert(outpoint_value, rune_balances.clone()))
+  {
+    remove_rune_holders(outpoint_value, &previous);
+  }
+  insert_rune_holders(outpoint_value, &rune_balances);
+  certification::mark_outpoint(outpoint_value);
 }
 
 pub(crate) fn mem_remove_outpoint_to_rune_balances(
   outpoint_value: OutPointValue,
 ) -> Option<RuneBalances> {
-  OUTPOINT_TO_RUNE_BALANCES.with(|m| m.borrow_mut().remove(&outpoint_value))
+  let rune_balances = OUTPOINT_TO_RUNE_BALANCES.with(|m| m.borrow_mut().remove(&outpoint_value))?;
+  remove_rune_holders(outpoint_value, &rune_balances);
+  certification::mark_outpoint(outpoint_value);
+  Some(rune_balances)
+}
+
+fn insert_rune_holders(outpoint_value: OutPointValue, rune_balances: &RuneBalances) {
+  for rune_balance in rune_balances.balances.iter() {
+    let rune_id_value = rune_balance.rune_id.store();
+    // a holder already in the index is counted, which makes backfilling it again harmless
+    if RUNE_ID_TO_OUTPOINTS
+      .with(|m| {
+        m.borrow_mut()
+          .insert((rune_id_value, outpoint_value), rune_balance.balance)
+      })
+      .is_some()
+    {
+      continue;
+    }
+    RUNE_ID_TO_OUTPOINT_COUNT.with(|m| {
+      let mut map = m.borrow_mut();
+      let count = map.get(&rune_id_value).unwrap_or(0);
+      map.insert(rune_id_value, count + 1);
+    });
+  }
+}
+
+fn remove_rune_holders(outpoint_value: OutPointValue, rune_balances: &RuneBalances) {
+  for rune_balance in rune_balances.balances.iter() {
+    let rune_id_value = rune_balance.rune_id.store();
+    if RUNE_ID_TO_OUTPOINTS
+      .with(|m| m.borrow_mut().remove(&(rune_id_value, outpoint_value)))
+      .is_none()
+    {
+      continue;
+    }
+    RUNE_ID_TO_OUTPOINT_COUNT.with(|m| {
+      let mut map = m.borrow_mut();
+      match map.get(&rune_id_value).unwrap_or(0) {
+        0 | 1 => map.remove(&rune_id_value),
+        count => map.insert(rune_id_value, count - 1),
+      }
+    });
+  }
+}
+
+/// Adds up to `limit` outpoints after `after` to the holder index, returns the last one added
+/// or `None` once every outpoint was scanned.
+pub(crate) fn backfill_rune_holders(
+  after: Option<OutPointValue>,
+  limit: usize,
+) -> Option<OutPointValue> {
+  let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+  let outpoints: Vec<(OutPointValue, RuneBalances)> = OUTPOINT_TO_RUNE_BALANCES.with(|m| {
+    m.borrow()
+      .range((start, Bound::Unbounded))
+      .take(limit)
+      .collect()
+  });
+  for (outpoint_value, rune_balances) in outpoints.iter() {
+    insert_rune_holders(*outpoint_value, rune_balances);
+  }
+  if outpoints.len() < limit {
+    return None;
+  }
+  outpoints.last().map(|(outpoint_value, _)| *outpoint_value)
+}
+
+pub fn mem_length_rune_id_to_outpoints() -> u64 {
+  RUNE_ID_TO_OUTPOINTS.with(|m| m.borrow().len())
+}
+
+/// Returns up to `limit` outpoints holding the rune, ordered by outpoint and starting after `after`.
+pub fn mem_get_rune_id_to_outpoints(
+  rune_id_value: RuneIdValue,
+  after: Option<OutPointValue>,
+  limit: usize,
+) -> Vec<(OutPointValue, u128)> {
+  let start = match after {
+    Some(outpoint_value) => Bound::Excluded((rune_id_value, outpoint_value)),
+    None => Bound::Included((rune_id_value, [0; 36])),
+  };
+  RUNE_ID_TO_OUTPOINTS.with(|m| {
+    m.borrow()
+      .range((start, Bound::Unbounded))
+      .take_while(|((id, _), _)| *id == rune_id_value)
+      .take(limit)
+      .map(|((_, outpoint_value), balance)| (outpoint_value, balance))
+      .collect()
+  })
+}
+
+pub fn mem_get_rune_id_to_outpoint_count(rune_id_value: RuneIdValue) -> u64 {
+  RUNE_ID_TO_OUTPOINT_COUNT.with(|m| m.borrow().get(&rune_id_value).unwrap_or(0))
 }
 
 pub fn mem_length_rune_id_to_rune_entry() -> u64 {
@@ -273,12 +600,68 @@ pub fn mem_get_rune_id_to_rune_entry(rune_id_value: RuneIdValue) -> Option<RuneE
 
 pub fn mem_insert_rune_id_to_rune_entry(rune_id_value: RuneIdValue, rune_entry: RuneEntry) {
   RUNE_ID_TO_RUNE_ENTRY.with(|m| m.borrow_mut().insert(rune_id_value, rune_entry));
+  certification::mark_rune_id(rune_id_value);
 }
 
 pub(crate) fn mem_remove_rune_id_to_rune_entry(rune_id_value: RuneIdValue) -> Option<RuneEntry> {
+  certification::mark_rune_id(rune_id_value);
   RUNE_ID_TO_RUNE_ENTRY.with(|m| m.borrow_mut().remove(&rune_id_value))
 }
 
+/// Returns up to `limit` rune entries ordered by rune id, i.e. by etching block, starting after `after`.
+pub fn mem_get_rune_id_to_rune_entries(
+  after: Option<RuneIdValue>,
+  limit: usize,
+) -> Vec<(RuneIdValue, RuneEntry)> {
+  let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+  RUNE_ID_TO_RUNE_ENTRY.with(|m| {
+    m.borrow()
+      .range((start, Bound::Unbounded))
+      .take(limit)
+      .collect()
+  })
+}
+
+pub fn mem_length_number_to_rune_id() -> u64 {
+  NUMBER_TO_RUNE_ID.with(|m| m.borrow().len())
+}
+
+/// Returns up to `limit` rune ids ordered by rune number, starting after `after`.
+pub fn mem_get_number_to_rune_ids(after: Option<u64>, limit: usize) -> Vec<(u64, RuneIdValue)> {
+  let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+  NUMBER_TO_RUNE_ID.with(|m| {
+    m.borrow()
+      .range((start, Bound::Unbounded))
+      .take(limit)
+      .collect()
+  })
+}
+
+pub fn mem_insert_number_to_rune_id(number: u64, rune_id_value: RuneIdValue) {
+  NUMBER_TO_RUNE_ID.with(|m| m.borrow_mut().insert(number, rune_id_value));
+}
+
+pub(crate) fn mem_remove_number_to_rune_id(number: u64) -> Option<RuneIdValue> {
+  NUMBER_TO_RUNE_ID.with(|m| m.borrow_mut().remove(&number))
+}
+
+/// Populates the number index from the rune entries of deployments that predate it.
+pub fn backfill_number_to_rune_id() {
+  if mem_length_number_to_rune_id() > 0 {
+    return;
+  }
+  RUNE_ID_TO_RUNE_ENTRY.with(|m| {
+    for (rune_id_value, rune_entry) in m.borrow().iter() {
+      mem_insert_number_to_rune_id(rune_entry.number, rune_id_value);
+    }
+  });
+  log!(
+    INFO,
+    "backfilled number index with {} runes",
+    mem_length_number_to_rune_id()
+  );
+}
+
 pub fn mem_length_rune_to_rune_id() -> u64 {
   RUNE_TO_RUNE_ID.with(|m| m.borrow().len())
 }
@@ -295,6 +678,79 @@ pub(crate) fn mem_remove_rune_to_rune_id(rune: u128) -> Option<RuneIdValue> {
   RUNE_TO_RUNE_ID.with(|m| m.borrow_mut().remove(&rune))
 }
 
+/// Returns up to `limit` runes whose names start with `prefix`, shortest names first and
+/// alphabetically within the same length.
+pub fn mem_search_runes(prefix: &[u8], limit: usize) -> Vec<(Rune, RuneIdValue)> {
+  let mut runes = Vec::new();
+  if prefix.is_empty() {
+    return runes;
+  }
+  for length in prefix.len()..=MAX_RUNE_NAME_LENGTH {
+    let Some((low, high)) = rune_prefix_range(prefix, length) else {
+      break;
+    };
+    let remaining = limit - runes.len();
+    RUNE_TO_RUNE_ID.with(|m| {
+      runes.extend(
+        m.borrow()
+          .range(low..=high)
+          .take(remaining)
+          .map(|(rune, rune_id_value)| (Rune(rune), rune_id_value)),
+      )
+    });
+    if runes.len() >= limit {
+      break;
+    }
+  }
+  runes
+}
+
+/// Length of `BCGDENLQRQWDSLRUGSNLBTMFIJAV`, the name of `u128::MAX`.
+const MAX_RUNE_NAME_LENGTH: usize = 28;
+
+/// Uppercases a user supplied rune name and drops spacers, or returns `None` if it cannot
+/// be part of a rune name.
+pub fn normalize_rune_name(name: &str) -> Option<Vec<u8>> {
+  name
+    .chars()
+    .filter(|c| !matches!(c, '•' | '.') && !c.is_whitespace())
+    .map(|c| {
+      let c = c.to_ascii_uppercase();
+      c.is_ascii_uppercase().then_some(c as u8)
+    })
+    .collect()
+}
+
+/// Returns the range of rune values whose names are `length` letters long and start with
+/// `prefix`. Rune names are bijective base-26, so names of equal length sort like their
+/// values and all names sharing a prefix form one contiguous range.
+fn rune_prefix_range(prefix: &[u8], length: usize) -> Option<(u128, u128)> {
+  let padding = length.checked_sub(prefix.len())?;
+  let padded = |letter: u8| {
+    rune_value(
+      prefix
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(letter).take(padding)),
+    )
+  };
+  let low = padded(b'A')?;
+  // names past u128::MAX do not exist, so the range is cut at the largest rune
+  let high = padded(b'Z').unwrap_or(u128::MAX);
+  Some((low, high))
+}
+
+fn rune_value(name: impl Iterator<Item = u8>) -> Option<u128> {
+  let mut x = 0u128;
+  for (i, c) in name.enumerate() {
+    if i > 0 {
+      x = x.checked_add(1)?;
+    }
+    x = x.checked_mul(26)?.checked_add((c - b'A').into())?;
+  }
+  Some(x)
+}
+
 pub fn mem_length_transaction_id_to_rune() -> u64 {
   TRANSACTION_ID_TO_RUNE.with(|m| m.borrow().len())
 }
@@ -317,12 +773,127 @@ pub fn mem_get_outpoint_to_height(outpoint: OutPointValue) -> Option<u32> {
 
 pub fn mem_insert_outpoint_to_height(outpoint: OutPointValue, height: u32) {
   OUTPOINT_TO_HEIGHT.with(|m| m.borrow_mut().insert(outpoint, height));
+  certification::mark_outpoint(outpoint);
 }
 
 pub(crate) fn mem_remove_outpoint_to_height(outpoint_value: OutPointValue) -> Option<u32> {
   OUTPOINT_TO_HEIGHT.with(|m| m.borrow_mut().remove(&outpoint_value))
 }
 
+pub fn mem_length_script_hash_to_outpoints() -> u64 {
+  SCRIPT_HASH_TO_OUTPOINTS.with(|m| m.borrow().len())
+}
+
+/// Returns up to `limit` rune-bearing outpoints locked by the script, ordered by outpoint and
+/// starting after `after`.
+pub fn mem_get_script_hash_to_outpoints(
+  script_hash: ScriptHashValue,
+  after: Option<OutPointValue>,
+  limit: usize,
+) -> Vec<OutPointValue> {
+  let start = match after {
+    Some(outpoint_value) => Bound::Excluded((script_hash, outpoint_value)),
+    None => Bound::Included((script_hash, [0; 36])),
+  };
+  SCRIPT_HASH_TO_OUTPOINTS.with(|m| {
+    m.borrow()
+      .range((start, Bound::Unbounded))
+      .take_while(|((s, _), _)| *s == script_hash)
+      .take(limit)
+      .map(|((_, outpoint), _)| outpoint)
+      .collect()
+  })
+}
+
+/// Also adds the balances of the outpoint to the totals of the script.
+pub fn mem_insert_script_hash_to_outpoint(
+  script_hash: ScriptHashValue,
+  outpoint: OutPointValue,
+  rune_balances: &RuneBalances,
+) {
+  if SCRIPT_HASH_TO_OUTPOINTS
+    .with(|m| m.borrow_mut().insert((script_hash, outpoint), ()))
+    .is_some()
+  {
+    return;
+  }
+  SCRIPT_HASH_TO_RUNE_TOTALS.with(|m| {
+    let mut map = m.borrow_mut();
+    for rune_balance in rune_balances.balances.iter() {
+      let key = (script_hash, rune_balance.rune_id.store());
+      let total = map.get(&key).unwrap_or(0);
+      map.insert(key, total.saturating_add(rune_balance.balance));
+    }
+  });
+}
+
+/// Also subtracts the balances of the outpoint from the totals of the script.
+pub(crate) fn mem_remove_script_hash_to_outpoint(
+  script_hash: ScriptHashValue,
+  outpoint: OutPointValue,
+  rune_balances: &RuneBalances,
+) -> Option<()> {
+  SCRIPT_HASH_TO_OUTPOINTS.with(|m| m.borrow_mut().remove(&(script_hash, outpoint)))?;
+  SCRIPT_HASH_TO_RUNE_TOTALS.with(|m| {
+    let mut map = m.borrow_mut();
+    for rune_balance in rune_balances.balances.iter() {
+      let key = (script_hash, rune_balance.rune_id.store());
+      match map
+        .get(&key)
+        .unwrap_or(0)
+        .saturating_sub(rune_balance.balance)
+      {
+        0 => map.remove(&key),
+        total => map.insert(key, total),
+      };
+    }
+  });
+  Some(())
+}
+
+pub fn mem_length_script_hash_to_rune_totals() -> u64 {
+  SCRIPT_HASH_TO_RUNE_TOTALS.with(|m| m.borrow().len())
+}
+
+/// Returns the balance of each rune held by the script, ordered by rune id.
+pub fn mem_get_script_hash_to_rune_totals(
+  script_hash: ScriptHashValue,
+) -> Vec<(RuneIdValue, u128)> {
+  SCRIPT_HASH_TO_RUNE_TOTALS.with(|m| {
+    m.borrow()
+      .range((script_hash, (0, 0))..)
+      .take_while(|((s, _), _)| *s == script_hash)
+      .map(|((_, rune_id_value), total)| (rune_id_value, total))
+      .collect()
+  })
+}
+
+pub fn mem_length_outpoint_to_script_hash() -> u64 {
+  OUTPOINT_TO_SCRIPT_HASH.with(|m| m.borrow().len())
+}
+
+pub fn mem_insert_outpoint_to_script_hash(outpoint: OutPointValue, script_hash: ScriptHashValue) {
+  OUTPOINT_TO_SCRIPT_HASH.with(|m| m.borrow_mut().insert(outpoint, script_hash));
+}
+
+pub(crate) fn mem_remove_outpoint_to_script_hash(
+  outpoint: OutPointValue,
+) -> Option<ScriptHashValue> {
+  OUTPOINT_TO_SCRIPT_HASH.with(|m| m.borrow_mut().remove(&outpoint))
+}
+
+/// Size in bytes of each memory, indexed by `MemoryId`.
+pub fn mem_memory_sizes() -> Vec<u64> {
+  MEMORY_MANAGER.with(|m| {
+    let memory_manager = m.borrow();
+    (0..MEMORY_COUNT)
+      .map(|id| {
+        ic_stable_structures::Memory::size(&memory_manager.get(MemoryId::new(id))) * WASM_PAGE_SIZE
+      })
+      .collect()
+  })
+}
+
 pub fn mem_length_change_record() -> u64 {
   HEIGHT_TO_CHANGE_RECORD.with(|m| m.borrow().len())
 }
@@ -353,6 +924,144 @@ pub fn mem_prune_change_record(height: u32) {
   });
 }
 
+pub fn mem_length_transaction_id_to_rune_transaction() -> u64 {
+  TRANSACTION_ID_TO_RUNE_TRANSACTION.with(|m| m.borrow().len())
+}
+
+pub fn mem_get_rune_transaction(txid: Txid) -> Option<RuneTransaction> {
+  TRANSACTION_ID_TO_RUNE_TRANSACTION.with(|m| m.borrow().get(&txid.store()))
+}
+
+pub(crate) fn mem_insert_rune_transaction(txid: Txid, rune_transaction: RuneTransaction) {
+  HEIGHT_TO_RUNE_TRANSACTIONS.with(|m| {
+    m.borrow_mut()
+      .insert((rune_transaction.height, txid.store()), ())
+  });
+  TRANSACTION_ID_TO_RUNE_TRANSACTION
+    .with(|m| m.borrow_mut().insert(txid.store(), rune_transaction));
+}
+
+/// Removes the rune transactions recorded at `height`.
+pub(crate) fn mem_remove_rune_transactions(height: u32) {
+  remove_rune_transactions((height, [0; 32])..=(height, [u8::MAX; 32]), usize::MAX);
+}
+
+/// Removes up to `MAX_PRUNED_KEYS` of the rune transactions recorded at or below `height`,
+/// oldest first.
+pub fn mem_prune_rune_transactions(height: u32) {
+  remove_rune_transactions(..=(height, [u8::MAX; 32]), MAX_PRUNED_KEYS);
+}
+
+fn remove_rune_transactions(range: impl std::ops::RangeBounds<(u32, TxidValue)>, limit: usize) {
+  let keys_to_remove: Vec<(u32, TxidValue)> = HEIGHT_TO_RUNE_TRANSACTIONS.with(|m| {
+    m.borrow()
+      .range(range)
+      .take(limit)
+      .map(|(key, _)| key)
+      .collect()
+  });
+  for key in keys_to_remove {
+    HEIGHT_TO_RUNE_TRANSACTIONS.with(|m| m.borrow_mut().remove(&key));
+    TRANSACTION_ID_TO_RUNE_TRANSACTION.with(|m| m.borrow_mut().remove(&key.1));
+  }
+}
+
+pub fn mem_length_block_runes_summary() -> u64 {
+  HEIGHT_TO_BLOCK_RUNES_SUMMARY.with(|m| m.borrow().len())
+}
+
+pub fn mem_get_block_runes_summary(height: u32) -> Option<BlockRunesSummary> {
+  HEIGHT_TO_BLOCK_RUNES_SUMMARY.with(|m| m.borrow().get(&height))
+}
+
+pub(crate) fn mem_insert_block_runes_summary(height: u32, summary: BlockRunesSummary) {
+  HEIGHT_TO_BLOCK_RUNES_SUMMARY.with(|m| m.borrow_mut().insert(height, summary));
+}
+
+pub(crate) fn mem_remove_block_runes_summary(height: u32) {
+  HEIGHT_TO_BLOCK_RUNES_SUMMARY.with(|m| m.borrow_mut().remove(&height));
+}
+
+pub fn mem_length_outpoint_to_spent_outpoint() -> u64 {
+  OUTPOINT_TO_SPENT_OUTPOINT.with(|m| m.borrow().len())
+}
+
+pub fn mem_get_outpoint_to_spent_outpoint(outpoint_value: OutPointValue) -> Option<SpentOutPoint> {
+  OUTPOINT_TO_SPENT_OUTPOINT.with(|m| m.borrow().get(&outpoint_value))
+}
+
+pub(crate) fn mem_insert_outpoint_to_spent_outpoint(
+  outpoint_value: OutPointValue,
+  spent_outpoint: SpentOutPoint,
+) {
+  HEIGHT_TO_SPENT_OUTPOINTS.with(|m| {
+    m.borrow_mut()
+      .insert((spent_outpoint.spent_height, outpoint_value), ())
+  });
+  OUTPOINT_TO_SPENT_OUTPOINT.with(|m| m.borrow_mut().insert(outpoint_value, spent_outpoint));
+}
+
+/// Removes the outpoints spent at `height`.
+pub(crate) fn mem_remove_spent_outpoints(height: u32) {
+  remove_spent_outpoints((height, [0; 36])..=(height, [u8::MAX; 36]), usize::MAX);
+}
+
+/// Removes up to `MAX_PRUNED_KEYS` of the outpoints spent at or below `height`, oldest first.
+pub fn mem_prune_spent_outpoints(height: u32) {
+  remove_spent_outpoints(..=(height, [u8::MAX; 36]), MAX_PRUNED_KEYS);
+}
+
+fn remove_spent_outpoints(range: impl std::ops::RangeBounds<(u32, OutPointValue)>, limit: usize) {
+  let keys_to_remove: Vec<(u32, OutPointValue)> = HEIGHT_TO_SPENT_OUTPOINTS.with(|m| {
+    m.borrow()
+      .range(range)
+      .take(limit)
+      .map(|(key, _)| key)
+      .collect()
+  });
+  for key in keys_to_remove {
+    HEIGHT_TO_SPENT_OUTPOINTS.with(|m| m.borrow_mut().remove(&key));
+    OUTPOINT_TO_SPENT_OUTPOINT.with(|m| m.borrow_mut().remove(&key.1));
+  }
+}
+
+pub fn mem_length_rune_id_height_to_supply() -> u64 {
+  RUNE_ID_HEIGHT_TO_SUPPLY.with(|m| m.borrow().len())
+}
+
+pub(crate) fn mem_insert_rune_id_height_to_supply(
+  rune_id_value: RuneIdValue,
+  height: u32,
+  mints: u128,
+  burned: u128,
+) {
+  RUNE_ID_HEIGHT_TO_SUPPLY.with(|m| {
+    m.borrow_mut()
+      .insert((rune_id_value, height), (mints, burned))
+  });
+}
+
+pub(crate) fn mem_remove_rune_id_height_to_supply(rune_id_value: RuneIdValue, height: u32) {
+  RUNE_ID_HEIGHT_TO_SUPPLY.with(|m| m.borrow_mut().remove(&(rune_id_value, height)));
+}
+
+/// Returns the (mints, burned) recorded before the first change after `height`, which are the
+/// values at the end of block `height`, or `None` if the rune did not change since.
+pub fn mem_get_rune_id_supply_after(
+  rune_id_value: RuneIdValue,
+  height: u32,
+) -> Option<(u128, u128)> {
+  RUNE_ID_HEIGHT_TO_SUPPLY.with(|m| {
+    m.borrow()
+      .range((
+        Bound::Excluded((rune_id_value, height)),
+        Bound::Included((rune_id_value, u32::MAX)),
+      ))
+      .next()
+      .map(|(_, supply)| supply)
+  })
+}
+
 pub fn mem_get_etching(txid: Txid) -> Option<(RuneId, RuneEntry)> {
   TRANSACTION_ID_TO_RUNE.with(|m| {
     m.borrow()
@@ -364,6 +1073,19 @@ pub fn mem_get_etching(txid: Txid) -> Option<(RuneId, RuneEntry)> {
   })
 }
 
+/// SHA-256 of the script, used to key the address index.
+pub fn script_hash(script: &Script) -> ScriptHashValue {
+  Sha256::digest(script.as_bytes()).into()
+}
+
+pub fn bitcoin_network(network: BitcoinNetwork) -> bitcoin::Network {
+  match network {
+    BitcoinNetwork::Mainnet => bitcoin::Network::Bitcoin,
+    BitcoinNetwork::Testnet => bitcoin::Network::Testnet4,
+    BitcoinNetwork::Regtest => bitcoin::Network::Regtest,
+  }
+}
+
 pub fn init_mainnet() {
   let rune = Rune(2055900680524219742);
 
@@ -371,6 +1093,7 @@ pub fn init_mainnet() {
   let etching = Txid::all_zeros();
 
   mem_insert_rune_to_rune_id(rune.store(), id.store());
+  mem_insert_number_to_rune_id(0, id.store());
   mem_insert_statistic_runes(1, 1);
 
   mem_insert_rune_id_to_rune_entry(
@@ -405,9 +1128,280 @@ pub fn init_mainnet() {
 pub fn next_block(network: BitcoinNetwork) -> (u32, Option<BlockHash>) {
   mem_latest_block()
     .map(|(height, prev_blockhash)| (height + 1, Some(prev_blockhash)))
-    .unwrap_or(match network {
-      BitcoinNetwork::Mainnet => (Rune::first_rune_height(bitcoin::Network::Bitcoin), None),
-      BitcoinNetwork::Testnet => (Rune::first_rune_height(bitcoin::Network::Testnet4), None),
-      BitcoinNetwork::Regtest => (Rune::first_rune_height(bitcoin::Network::Regtest), None),
-    })
+    .unwrap_or((Rune::first_rune_height(bitcoin_network(network)), None))
+}
+
+/// Fixtures shared by the tests of the index and its submodules.
+#[cfg(test)]
+pub(crate) mod test_helpers {
+  use super::*;
+
+  /// Balances of the runes `block:0` with the given amounts.
+  pub(crate) fn rune_balances(balances: &[(u64, u128)]) -> RuneBalances {
+    RuneBalances {
+      balances: balances
+        .iter()
+        .map(|(block, balance)| entry::RuneBalance {
+          rune_id: RuneId {
+            block: *block,
+            tx: 0,
+          },
+          balance: *balance,
+        })
+        .collect(),
+    }
+  }
+
+  /// Stores an unspent output created at `height` holding `balances`, see `rune_balances`.
+  pub(crate) fn insert_outpoint(
+    outpoint_value: OutPointValue,
+    height: u32,
+    balances: &[(u64, u128)],
+  ) {
+    mem_insert_outpoint_to_rune_balances(outpoint_value, rune_balances(balances));
+    mem_insert_outpoint_to_height(outpoint_value, height);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::test_helpers::*;
+  use super::*;
+  use std::str::FromStr;
+
+  fn value(name: &str) -> u128 {
+    Rune::from_str(name).unwrap().0
+  }
+
+  #[test]
+  fn normalize_rune_name() {
+    assert_eq!(
+      super::normalize_rune_name("dog•go to.the MOON"),
+      Some(b"DOGGOTOTHEMOON".to_vec())
+    );
+    assert_eq!(super::normalize_rune_name("DOG1"), None);
+  }
+
+  #[test]
+  fn rune_prefix_range() {
+    assert_eq!(
+      super::rune_prefix_range(b"DOG", 3),
+      Some((value("DOG"), value("DOG")))
+    );
+    assert_eq!(
+      super::rune_prefix_range(b"DOG", 5),
+      Some((value("DOGAA"), value("DOGZZ")))
+    );
+    assert_eq!(super::rune_prefix_range(b"DOG", 2), None);
+  }
+
+  #[test]
+  fn rune_prefix_range_is_cut_at_max_rune() {
+    assert_eq!(
+      super::rune_prefix_range(b"BCGDENLQRQWDSLRUGSNLBTMFIJ", 28),
+      Some((value("BCGDENLQRQWDSLRUGSNLBTMFIJAA"), u128::MAX))
+    );
+    assert_eq!(super::rune_prefix_range(b"Z", 28), None);
+  }
+
+  #[test]
+  fn search_runes_shortest_names_first() {
+    for (block, name) in ["DOGAA", "DOGZ", "CAT", "DOG", "DO", "DOGE"]
+      .into_iter()
+      .enumerate()
+    {
+      mem_insert_rune_to_rune_id(value(name), (block as u64, 0));
+    }
+
+    let names = |prefix: &[u8], limit| {
+      mem_search_runes(prefix, limit)
+        .into_iter()
+        .map(|(rune, _)| rune.to_string())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(names(b"DOG", 10), ["DOG", "DOGE", "DOGZ", "DOGAA"]);
+    assert_eq!(names(b"DOG", 2), ["DOG", "DOGE"]);
+    assert_eq!(names(b"D", 10), ["DO", "DOG", "DOGE", "DOGZ", "DOGAA"]);
+    assert!(names(b"", 10).is_empty());
+    assert!(names(b"DOGX", 10).is_empty());
+  }
+
+  #[test]
+  fn script_hash_outpoints_page_starts_after_cursor() {
+    let script_hash = [1; 32];
+    let outpoints = [[1; 36], [2; 36], [3; 36]];
+    for outpoint in outpoints {
+      mem_insert_script_hash_to_outpoint(script_hash, outpoint, &rune_balances(&[(1, 1)]));
+    }
+    // neighbouring scripts must not leak into the page
+    mem_insert_script_hash_to_outpoint([0; 32], [9; 36], &rune_balances(&[(1, 1)]));
+    mem_insert_script_hash_to_outpoint([2; 32], [0; 36], &rune_balances(&[(1, 1)]));
+
+    assert_eq!(
+      mem_get_script_hash_to_outpoints(script_hash, None, 2),
+      outpoints[..2]
+    );
+    assert_eq!(
+      mem_get_script_hash_to_outpoints(script_hash, Some(outpoints[1]), 2),
+      outpoints[2..]
+    );
+    assert_eq!(
+      mem_get_script_hash_to_outpoints(script_hash, Some(outpoints[2]), 2),
+      Vec::<OutPointValue>::new()
+    );
+    assert_eq!(
+      mem_get_script_hash_to_outpoints(script_hash, None, 0),
+      Vec::<OutPointValue>::new()
+    );
+  }
+
+  #[test]
+  fn script_hash_outpoints_page_after_removed_cursor() {
+    let script_hash = [1; 32];
+    let mut between = [1; 36];
+    between[35] = 2;
+    for outpoint in [[1; 36], between, [2; 36]] {
+      mem_insert_script_hash_to_outpoint(script_hash, outpoint, &rune_balances(&[(1, 1)]));
+    }
+    // a cursor whose outpoint was spent since still resumes at the next outpoint
+    mem_remove_script_hash_to_outpoint(script_hash, between, &rune_balances(&[(1, 1)]));
+
+    assert_eq!(
+      mem_get_script_hash_to_outpoints(script_hash, Some(between), 10),
+      vec![[2; 36]]
+    );
+    assert_eq!(
+      mem_get_script_hash_to_outpoints(script_hash, Some([0; 36]), 10),
+      vec![[1; 36], [2; 36]]
+    );
+  }
+
+  #[test]
+  fn script_hash_rune_totals_follow_outpoints() {
+    let script_hash = [1; 32];
+    mem_insert_script_hash_to_outpoint(script_hash, [1; 36], &rune_balances(&[(1, 10), (2, 5)]));
+    mem_insert_script_hash_to_outpoint(script_hash, [2; 36], &rune_balances(&[(1, 7)]));
+    // inserting an outpoint twice must not count its balances twice
+    mem_insert_script_hash_to_outpoint(script_hash, [2; 36], &rune_balances(&[(1, 7)]));
+    mem_insert_script_hash_to_outpoint([2; 32], [3; 36], &rune_balances(&[(1, 100)]));
+
+    assert_eq!(
+      mem_get_script_hash_to_rune_totals(script_hash),
+      vec![((1, 0), 17), ((2, 0), 5)]
+    );
+
+    mem_remove_script_hash_to_outpoint(script_hash, [1; 36], &rune_balances(&[(1, 10), (2, 5)]));
+    assert_eq!(
+      mem_get_script_hash_to_rune_totals(script_hash),
+      vec![((1, 0), 7)]
+    );
+
+    // removing an outpoint the script does not hold leaves the totals alone
+    assert_eq!(
+      mem_remove_script_hash_to_outpoint(script_hash, [1; 36], &rune_balances(&[(1, 10)])),
+      None
+    );
+    mem_remove_script_hash_to_outpoint(script_hash, [2; 36], &rune_balances(&[(1, 7)]));
+    assert!(mem_get_script_hash_to_rune_totals(script_hash).is_empty());
+    assert_eq!(
+      mem_get_script_hash_to_rune_totals([2; 32]),
+      vec![((1, 0), 100)]
+    );
+  }
+
+  #[test]
+  fn rune_transaction_pruning_is_capped() {
+    let rune_transaction = |height| RuneTransaction {
+      height,
+      tx_index: 0,
+      artifact: None,
+      inputs: Vec::new(),
+      minted: None,
+      etched: None,
+      pointer: None,
+      allocations: Vec::new(),
+      burned: Vec::new(),
+    };
+    for (n, height) in [1, 1, 2, 2, 3].into_iter().enumerate() {
+      mem_insert_rune_transaction(
+        Txid::from_byte_array([n as u8; 32]),
+        rune_transaction(height),
+      );
+    }
+
+    remove_rune_transactions(..=(2, [u8::MAX; 32]), 3);
+    assert_eq!(mem_length_transaction_id_to_rune_transaction(), 2);
+    assert!(mem_get_rune_transaction(Txid::from_byte_array([2; 32])).is_none());
+    assert!(mem_get_rune_transaction(Txid::from_byte_array([3; 32])).is_some());
+
+    remove_rune_transactions(..=(2, [u8::MAX; 32]), 3);
+    assert_eq!(mem_length_transaction_id_to_rune_transaction(), 1);
+
+    mem_remove_rune_transactions(3);
+    assert_eq!(mem_length_transaction_id_to_rune_transaction(), 0);
+    HEIGHT_TO_RUNE_TRANSACTIONS.with(|m| assert!(m.borrow().is_empty()));
+  }
+
+  #[test]
+  fn spent_outpoint_pruning_is_capped() {
+    for (n, spent_height) in [1, 1, 2, 3].into_iter().enumerate() {
+      mem_insert_outpoint_to_spent_outpoint(
+        [n as u8; 36],
+        SpentOutPoint {
+          rune_balances: rune_balances(&[(1, 1)]),
+          height: 0,
+          spent_height,
+          spender: Txid::all_zeros(),
+        },
+      );
+    }
+
+    remove_spent_outpoints(..=(2, [u8::MAX; 36]), 2);
+    assert_eq!(mem_length_outpoint_to_spent_outpoint(), 2);
+    assert!(mem_get_outpoint_to_spent_outpoint([1; 36]).is_none());
+    assert!(mem_get_outpoint_to_spent_outpoint([2; 36]).is_some());
+
+    remove_spent_outpoints(..=(2, [u8::MAX; 36]), 2);
+    assert_eq!(mem_length_outpoint_to_spent_outpoint(), 1);
+
+    mem_remove_spent_outpoints(3);
+    assert_eq!(mem_length_outpoint_to_spent_outpoint(), 0);
+    HEIGHT_TO_SPENT_OUTPOINTS.with(|m| assert!(m.borrow().is_empty()));
+  }
+
+  #[test]
+  fn rune_holders_follow_balances() {
+    mem_insert_outpoint_to_rune_balances([1; 36], rune_balances(&[(1, 10), (2, 5)]));
+    mem_insert_outpoint_to_rune_balances([2; 36], rune_balances(&[(1, 7)]));
+    assert_eq!(mem_get_rune_id_to_outpoint_count((1, 0)), 2);
+    assert_eq!(mem_get_rune_id_to_outpoint_count((2, 0)), 1);
+
+    // replacing the balances of an outpoint moves it between runes
+    mem_insert_outpoint_to_rune_balances([1; 36], rune_balances(&[(2, 15)]));
+    assert_eq!(mem_get_rune_id_to_outpoint_count((1, 0)), 1);
+    assert_eq!(
+      mem_get_rune_id_to_outpoints((2, 0), None, 10),
+      vec![([1; 36], 15)]
+    );
+
+    mem_remove_outpoint_to_rune_balances([1; 36]);
+    mem_remove_outpoint_to_rune_balances([2; 36]);
+    assert_eq!(mem_get_rune_id_to_outpoint_count((1, 0)), 0);
+    assert_eq!(mem_get_rune_id_to_outpoint_count((2, 0)), 0);
+    assert_eq!(mem_length_rune_id_to_outpoints(), 0);
+    RUNE_ID_TO_OUTPOINT_COUNT.with(|m| assert!(m.borrow().is_empty()));
+  }
+
+  #[test]
+  fn address_index_start_is_recorded_once() {
+    // a deployment that indexed blocks before the address index existed
+    mem_insert_block_header(100, [0; 80]);
+    start_address_index();
+    assert_eq!(mem_get_address_index_start(), 101);
+
+    // later upgrades keep it
+    mem_insert_block_header(101, [0; 80]);
+    start_address_index();
+    assert_eq!(mem_get_address_index_start(), 101);
+  }
 }
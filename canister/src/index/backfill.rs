@@ -0,0 +1,192 @@
+use super::*;
+use crate::index::entry::Versioned;
+use ic_stable_structures::storable::{Bound, Storable};
+use runes_indexer_interface::certification::TREE_DEPTH;
+use std::borrow::Cow;
+use std::time::Duration;
+
+/// Entries scanned per step, a step stays far below the instruction limit of a message.
+const BATCH: usize = 500;
+/// Steps run in one message until it used this many instructions.
+const INSTRUCTION_BUDGET: u64 = 10_000_000_000;
+
+/// Progress of populating indices a deployment predates, each stage is resumed after its
+/// cursor. The index loop waits until it is done, so blocks never change what is scanned.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Backfill {
+  #[default]
+  RuneHolders,
+  RuneHoldersAfter(OutPoint),
+  CertifiedRunes,
+  CertifiedRunesAfter(RuneId),
+  CertifiedOutPoints,
+  CertifiedOutPointsAfter(OutPoint),
+  /// Nodes of the certified tree, rebuilt level by level once all leaves are set.
+  CertifiedNodes {
+    level: u32,
+    after: Option<u32>,
+  },
+  Done,
+}
+
+impl Versioned for Backfill {
+  const VERSION: u8 = 1;
+}
+
+impl Storable for Backfill {
+  fn to_bytes(&self) -> Cow<[u8]> {
+    Cow::Owned(self.to_versioned_bytes())
+  }
+
+  fn from_bytes(bytes: Cow<[u8]>) -> Self {
+    Self::from_versioned_bytes(&bytes)
+  }
+
+  const BOUND: Bound = Bound::Unbounded;
+}
+
+pub fn is_done() -> bool {
+  crate::index::mem_get_backfill() == Backfill::Done
+}
+
+/// Marks a fresh deployment, which has nothing to backfill, as done.
+pub fn skip() {
+  crate::index::mem_set_backfill(Backfill::Done);
+}
+
+/// Runs the remaining backfill on timers, one message after the other.
+pub fn resume() {
+  if is_done() {
+    return;
+  }
+  ic_cdk_timers::set_timer(Duration::ZERO, || {
+    while ic_cdk::api::performance_counter(0) < INSTRUCTION_BUDGET {
+      if step(BATCH) {
+        crate::index::certification::certify();
+        return;
+      }
+    }
+    resume();
+  });
+}
+
+/// Scans up to `limit` entries of the current stage, returns whether the backfill is done.
+pub(crate) fn step(limit: usize) -> bool {
+  let backfill = match crate::index::mem_get_backfill() {
+    Backfill::RuneHolders => rune_holders(None, limit),
+    Backfill::RuneHoldersAfter(outpoint) => rune_holders(Some(outpoint.store()), limit),
+    Backfill::CertifiedRunes => certified_runes(None, limit),
+    Backfill::CertifiedRunesAfter(rune_id) => certified_runes(Some(rune_id.store()), limit),
+    Backfill::CertifiedOutPoints => certified_outpoints(None, limit),
+    Backfill::CertifiedOutPointsAfter(outpoint) => {
+      certified_outpoints(Some(outpoint.store()), limit)
+    }
+    Backfill::CertifiedNodes { level, after } => certified_nodes(level, after, limit),
+    Backfill::Done => return true,
+  };
+  let done = backfill == Backfill::Done;
+  crate::index::mem_set_backfill(backfill);
+  done
+}
+
+fn rune_holders(after: Option<OutPointValue>, limit: usize) -> Backfill {
+  match crate::index::backfill_rune_holders(after, limit) {
+    Some(outpoint_value) => Backfill::RuneHoldersAfter(OutPoint::load(outpoint_value)),
+    None => {
+      log!(
+        INFO,
+        "backfilled holder index with {} holders",
+        crate::index::mem_length_rune_id_to_outpoints()
+      );
+      Backfill::CertifiedRunes
+    }
+  }
+}
+
+fn certified_runes(after: Option<RuneIdValue>, limit: usize) -> Backfill {
+  match certification::backfill_rune_leaves(after, limit) {
+    Some(rune_id_value) => Backfill::CertifiedRunesAfter(RuneId::load(rune_id_value)),
+    None => Backfill::CertifiedOutPoints,
+  }
+}
+
+fn certified_outpoints(after: Option<OutPointValue>, limit: usize) -> Backfill {
+  match certification::backfill_outpoint_leaves(after, limit) {
+    Some(outpoint_value) => Backfill::CertifiedOutPointsAfter(OutPoint::load(outpoint_value)),
+    None => Backfill::CertifiedNodes {
+      level: 0,
+      after: None,
+    },
+  }
+}
+
+fn certified_nodes(level: u32, after: Option<u32>, limit: usize) -> Backfill {
+  match certification::backfill_nodes(level, after, limit) {
+    Some(index) => Backfill::CertifiedNodes {
+      level,
+      after: Some(index),
+    },
+    None if level < TREE_DEPTH => Backfill::CertifiedNodes {
+      level: level + 1,
+      after: None,
+    },
+    None => {
+      log!(
+        INFO,
+        "backfilled certified tree with {} leaves",
+        certification::length_leaves()
+      );
+      Backfill::Done
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::index::test_helpers::rune_balances;
+
+  fn insert_unindexed(outpoint_value: OutPointValue, balances: &[(u64, u128)]) {
+    // bypasses the holder index like the balances of a deployment that predates it
+    OUTPOINT_TO_RUNE_BALANCES.with(|m| {
+      m.borrow_mut()
+        .insert(outpoint_value, rune_balances(balances))
+    });
+  }
+
+  #[test]
+  fn rune_holders_are_backfilled_in_steps() {
+    for n in 1..=5 {
+      insert_unindexed([n; 36], &[(1, n.into())]);
+    }
+    insert_unindexed([6; 36], &[(1, 6), (2, 6)]);
+    // already in the holder index, must not be counted twice
+    mem_insert_outpoint_to_rune_balances(
+      [3; 36],
+      mem_get_outpoint_to_rune_balances([3; 36]).unwrap(),
+    );
+
+    assert!(!step(4));
+    assert_eq!(
+      mem_get_backfill(),
+      Backfill::RuneHoldersAfter(OutPoint::load([4; 36]))
+    );
+    assert!(!step(4));
+    assert_eq!(mem_get_backfill(), Backfill::CertifiedRunes);
+    while !step(4) {}
+
+    assert_eq!(mem_get_rune_id_to_outpoint_count((1, 0)), 6);
+    assert_eq!(mem_get_rune_id_to_outpoint_count((2, 0)), 1);
+    assert_eq!(
+      mem_get_rune_id_to_outpoints((1, 0), Some([5; 36]), 10),
+      vec![([6; 36], 6)]
+    );
+  }
+
+  #[test]
+  fn fresh_deployment_skips_backfill() {
+    assert!(!is_done());
+    skip();
+    assert!(step(1));
+  }
+}
@@ -0,0 +1,412 @@
+use super::*;
+use runes_indexer_interface::certification::{self, Hash, Witness, TREE_DEPTH};
+use std::collections::BTreeSet;
+
+thread_local! {
+  // keys changed since the last commit, their leaves are recomputed from the current state
+  static DIRTY_OUTPOINTS: RefCell<BTreeSet<OutPointValue>> = RefCell::new(BTreeSet::new());
+  static DIRTY_RUNE_IDS: RefCell<BTreeSet<RuneIdValue>> = RefCell::new(BTreeSet::new());
+}
+
+pub(super) fn mark_outpoint(outpoint_value: OutPointValue) {
+  DIRTY_OUTPOINTS.with(|d| d.borrow_mut().insert(outpoint_value));
+}
+
+pub(super) fn mark_rune_id(rune_id_value: RuneIdValue) {
+  DIRTY_RUNE_IDS.with(|d| d.borrow_mut().insert(rune_id_value));
+}
+
+pub fn length_leaves() -> u64 {
+  CERTIFIED_LEAVES.with(|m| m.borrow().len())
+}
+
+/// Updates the tree with the keys changed since the last commit and certifies the new root
+/// together with the latest block.
+pub(crate) fn commit() {
+  update();
+  certify();
+}
+
+fn update() {
+  let outpoints = DIRTY_OUTPOINTS.with(|d| std::mem::take(&mut *d.borrow_mut()));
+  let rune_ids = DIRTY_RUNE_IDS.with(|d| std::mem::take(&mut *d.borrow_mut()));
+
+  let mut buckets = BTreeSet::new();
+
+  for outpoint_value in outpoints {
+    let (key, value) = outpoint_leaf(
+      outpoint_value,
+      mem_get_outpoint_to_rune_balances(outpoint_value),
+    );
+    buckets.insert(set_leaf(key, value));
+  }
+
+  for rune_id_value in rune_ids {
+    let (key, value) = rune_leaf(rune_id_value, mem_get_rune_id_to_rune_entry(rune_id_value));
+    buckets.insert(set_leaf(key, value));
+  }
+
+  if !buckets.is_empty() {
+    let empty = certification::empty_hashes();
+    for bucket in buckets {
+      update_path(bucket, &empty);
+    }
+  }
+}
+
+/// Sets the certified data to the current root and latest block.
+pub fn certify() {
+  if let Some((height, block_hash)) = mem_latest_block() {
+    ic_cdk::api::set_certified_data(&certification::certified_data(
+      height,
+      &block_hash.to_string(),
+      &root(),
+    ));
+  }
+}
+
+fn root() -> Hash {
+  node(TREE_DEPTH, 0, &certification::empty_hashes())
+}
+
+/// Sets the leaves of up to `limit` rune entries after `after`, returns the last one if there
+/// may be more.
+pub(super) fn backfill_rune_leaves(
+  after: Option<RuneIdValue>,
+  limit: usize,
+) -> Option<RuneIdValue> {
+  let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+  let entries: Vec<(RuneIdValue, RuneEntry)> = RUNE_ID_TO_RUNE_ENTRY.with(|m| {
+    m.borrow()
+      .range((start, Bound::Unbounded))
+      .take(limit)
+      .collect()
+  });
+  for (rune_id_value, entry) in entries.iter() {
+    let (key, value) = rune_leaf(*rune_id_value, Some(*entry));
+    set_leaf(key, value);
+  }
+  if entries.len() < limit {
+    return None;
+  }
+  entries.last().map(|(rune_id_value, _)| *rune_id_value)
+}
+
+/// Sets the leaves of up to `limit` outpoint balances after `after`, returns the last one if
+/// there may be more.
+pub(super) fn backfill_outpoint_leaves(
+  after: Option<OutPointValue>,
+  limit: usize,
+) -> Option<OutPointValue> {
+  let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+  let outpoints: Vec<(OutPointValue, RuneBalances)> = OUTPOINT_TO_RUNE_BALANCES.with(|m| {
+    m.borrow()
+      .range((start, Bound::Unbounded))
+      .take(limit)
+      .collect()
+  });
+  let len = outpoints.len();
+  let last = outpoints.last().map(|(outpoint_value, _)| *outpoint_value);
+  for (outpoint_value, rune_balances) in outpoints {
+    let (key, value) = outpoint_leaf(outpoint_value, Some(rune_balances));
+    set_leaf(key, value);
+  }
+  if len < limit {
+    return None;
+  }
+  last
+}
+
+/// Recomputes up to `limit` nonempty nodes of `level` after index `after` from the level below,
+/// or from the leaves for buckets, and returns the last one if there may be more. A level must
+/// be done before the one above it is started.
+pub(super) fn backfill_nodes(level: u32, after: Option<u32>, limit: usize) -> Option<u32> {
+  let empty = certification::empty_hashes();
+  let mut start = after.map_or(0, |index| index + 1);
+  let mut last = None;
+  for _ in 0..limit {
+    let index = next_nonempty(level, start)?;
+    let hash = if level == 0 {
+      certification::bucket_hash(&bucket_leaves(index))
+    } else {
+      certification::node_hash(
+        &node(level - 1, index << 1, &empty),
+        &node(level - 1, (index << 1) | 1, &empty),
+      )
+    };
+    set_node(level, index, hash, &empty);
+    last = Some(index);
+    start = index + 1;
+  }
+  last
+}
+
+/// First index from `start` on at `level` that has a leaf below it.
+fn next_nonempty(level: u32, start: u32) -> Option<u32> {
+  if level == 0 {
+    CERTIFIED_LEAVES
+      .with(|m| m.borrow().range((start, [0; 32])..).next())
+      .map(|((bucket, _), _)| bucket)
+  } else {
+    let child = level as u8 - 1;
+    CERTIFIED_NODES
+      .with(|m| m.borrow().range((child, start << 1)..).next())
+      .filter(|((node_level, _), _)| *node_level == child)
+      .map(|((_, index), _)| index >> 1)
+  }
+}
+
+pub fn outpoint_witness(outpoint: OutPoint) -> Witness {
+  witness(certification::outpoint_key(&outpoint.to_string()))
+}
+
+pub fn rune_witness(rune_id: RuneId) -> Witness {
+  witness(certification::rune_key(&rune_id.to_string()))
+}
+
+fn witness(key: Hash) -> Witness {
+  let empty = certification::empty_hashes();
+  let mut index = certification::bucket_index(&key);
+  let bucket = bucket_leaves(index);
+
+  let mut siblings = Vec::with_capacity(TREE_DEPTH as usize);
+  for level in 0..TREE_DEPTH {
+    siblings.push(node(level, index ^ 1, &empty));
+    index >>= 1;
+  }
+
+  Witness { bucket, siblings }
+}
+
+fn rune_balances_hash(rune_balances: &RuneBalances, height: u32) -> Hash {
+  certification::rune_balances_hash(
+    height,
+    rune_balances
+      .balances
+      .iter()
+      .map(|rune_balance| (rune_balance.rune_id.to_string(), rune_balance.balance)),
+  )
+}
+
+fn outpoint_leaf(
+  outpoint_value: OutPointValue,
+  rune_balances: Option<RuneBalances>,
+) -> (Hash, Option<Hash>) {
+  let value = rune_balances
+    .zip(mem_get_outpoint_to_height(outpoint_value))
+    .map(|(rune_balances, height)| rune_balances_hash(&rune_balances, height));
+  (
+    certification::outpoint_key(&OutPoint::load(outpoint_value).to_string()),
+    value,
+  )
+}
+
+fn rune_leaf(rune_id_value: RuneIdValue, entry: Option<RuneEntry>) -> (Hash, Option<Hash>) {
+  let rune_id = RuneId::load(rune_id_value);
+  (
+    certification::rune_key(&rune_id.to_string()),
+    entry.map(|entry| certification::rune_entry_hash(&entry.to_interface(rune_id, 0, 0))),
+  )
+}
+
+fn set_leaf(key: Hash, value: Option<Hash>) -> u32 {
+  let bucket = certification::bucket_index(&key);
+  CERTIFIED_LEAVES.with(|m| match value {
+    Some(value) => m.borrow_mut().insert((bucket, key), value),
+    None => m.borrow_mut().remove(&(bucket, key)),
+  });
+  bucket
+}
+
+fn bucket_leaves(bucket: u32) -> Vec<(Hash, Hash)> {
+  CERTIFIED_LEAVES.with(|m| {
+    m.borrow()
+      .range((bucket, [0; 32])..=(bucket, [u8::MAX; 32]))
+      .map(|((_, key), value)| (key, value))
+      .collect()
+  })
+}
+
+fn update_path(bucket: u32, empty: &[Hash]) {
+  let mut index = bucket;
+  let mut hash = certification::bucket_hash(&bucket_leaves(bucket));
+  for level in 0..TREE_DEPTH {
+    set_node(level, index, hash, empty);
+    let sibling = node(level, index ^ 1, empty);
+    hash = if index & 1 == 0 {
+      certification::node_hash(&hash, &sibling)
+    } else {
+      certification::node_hash(&sibling, &hash)
+    };
+    index >>= 1;
+  }
+  set_node(TREE_DEPTH, 0, hash, empty);
+}
+
+fn node(level: u32, index: u32, empty: &[Hash]) -> Hash {
+  CERTIFIED_NODES
+    .with(|m| m.borrow().get(&(level as u8, index)))
+    .unwrap_or(empty[level as usize])
+}
+
+fn set_node(level: u32, index: u32, hash: Hash, empty: &[Hash]) {
+  CERTIFIED_NODES.with(|m| {
+    if hash == empty[level as usize] {
+      m.borrow_mut().remove(&(level as u8, index));
+    } else {
+      m.borrow_mut().insert((level as u8, index), hash);
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::index::test_helpers::insert_outpoint;
+  use std::collections::BTreeMap;
+
+  const RUNE: RuneIdValue = (1, 0);
+
+  fn outpoint_key(outpoint_value: OutPointValue) -> Hash {
+    certification::outpoint_key(&OutPoint::load(outpoint_value).to_string())
+  }
+
+  fn verifies(key: Hash, value: Option<Hash>) -> bool {
+    certification::witness_root(&key, value.as_ref(), &witness(key)) == Some(root())
+  }
+
+  #[test]
+  fn leaves_hash_the_returned_values() {
+    init_mainnet();
+    insert_outpoint([1; 36], 100, &[(RUNE.0, 5)]);
+    update();
+
+    let entry = mem_get_rune_id_to_rune_entry(RUNE).unwrap();
+    // confirmations and holders are returned but not certified
+    let value = certification::rune_entry_hash(&entry.to_interface(RuneId::load(RUNE), 5, 7));
+    assert_eq!(rune_leaf(RUNE, Some(entry)).1, Some(value));
+    assert!(verifies(certification::rune_key("1:0"), Some(value)));
+
+    let balances = vec![runes_indexer_interface::RuneBalance {
+      confirmations: 11,
+      rune_id: "1:0".into(),
+      amount: 5,
+      divisibility: 0,
+      symbol: Some("\u{29C9}".into()),
+    }];
+    let value = certification::rune_balances_value(110, Some(&balances));
+    assert_eq!(
+      outpoint_leaf([1; 36], mem_get_outpoint_to_rune_balances([1; 36])).1,
+      value
+    );
+    assert!(verifies(outpoint_key([1; 36]), value));
+  }
+
+  #[test]
+  fn witnesses_prove_present_and_absent_keys() {
+    for n in 1..=20 {
+      insert_outpoint([n; 36], n.into(), &[(RUNE.0, n.into())]);
+    }
+    update();
+    mem_remove_outpoint_to_rune_balances([3; 36]);
+    update();
+
+    for n in 1..=20 {
+      let (key, value) = outpoint_leaf([n; 36], mem_get_outpoint_to_rune_balances([n; 36]));
+      assert_eq!(value.is_none(), n == 3);
+      assert!(verifies(key, value));
+      assert!(!verifies(key, Some([0; 32])));
+    }
+    assert!(verifies(outpoint_key([21; 36]), None));
+    assert!(!verifies(outpoint_key([1; 36]), None));
+  }
+
+  #[test]
+  fn update_path_matches_a_full_fold() {
+    let empty = certification::empty_hashes();
+    let last = (1 << TREE_DEPTH) - 1;
+    let mut bucket_one = [0; 32];
+    bucket_one[2] = 0x10;
+    let mut bucket_zero = [0; 32];
+    bucket_zero[31] = 1;
+    let keys = [[0; 32], bucket_zero, bucket_one, [u8::MAX; 32]];
+    assert_eq!(
+      keys
+        .iter()
+        .map(certification::bucket_index)
+        .collect::<Vec<_>>(),
+      [0, 0, 1, last]
+    );
+
+    for (n, key) in keys.iter().enumerate() {
+      update_path(set_leaf(*key, Some([n as u8; 32])), &empty);
+    }
+
+    let mut nodes: BTreeMap<u32, Hash> = [0, 1, last]
+      .into_iter()
+      .map(|bucket| (bucket, certification::bucket_hash(&bucket_leaves(bucket))))
+      .collect();
+    for level in 0..TREE_DEPTH as usize {
+      let child = |index| nodes.get(&index).copied().unwrap_or(empty[level]);
+      let parents: BTreeMap<u32, Hash> = nodes
+        .keys()
+        .map(|index| index >> 1)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|parent| {
+          (
+            parent,
+            certification::node_hash(&child(parent << 1), &child((parent << 1) | 1)),
+          )
+        })
+        .collect();
+      nodes = parents;
+    }
+    assert_eq!(nodes.into_iter().collect::<Vec<_>>(), [(0, root())]);
+
+    for key in keys {
+      update_path(set_leaf(key, None), &empty);
+    }
+    assert_eq!(root(), empty[TREE_DEPTH as usize]);
+    assert_eq!(CERTIFIED_NODES.with(|m| m.borrow().len()), 0);
+  }
+
+  #[test]
+  fn backfilled_tree_matches_the_committed_tree() {
+    init_mainnet();
+    for n in 1..=20 {
+      insert_outpoint([n; 36], n.into(), &[(RUNE.0, n.into())]);
+    }
+    update();
+    let committed = root();
+
+    // a tree that predates the entries
+    let leaves: Vec<_> = CERTIFIED_LEAVES.with(|m| m.borrow().iter().map(|(k, _)| k).collect());
+    let nodes: Vec<_> = CERTIFIED_NODES.with(|m| m.borrow().iter().map(|(k, _)| k).collect());
+    for key in leaves {
+      CERTIFIED_LEAVES.with(|m| m.borrow_mut().remove(&key));
+    }
+    for key in nodes {
+      CERTIFIED_NODES.with(|m| m.borrow_mut().remove(&key));
+    }
+    assert_ne!(root(), committed);
+
+    let mut rune_id = backfill_rune_leaves(None, 3);
+    while rune_id.is_some() {
+      rune_id = backfill_rune_leaves(rune_id, 3);
+    }
+    let mut outpoint = backfill_outpoint_leaves(None, 3);
+    while outpoint.is_some() {
+      outpoint = backfill_outpoint_leaves(outpoint, 3);
+    }
+    for level in 0..=TREE_DEPTH {
+      let mut index = backfill_nodes(level, None, 3);
+      while index.is_some() {
+        index = backfill_nodes(level, index, 3);
+      }
+    }
+
+    assert_eq!(length_leaves(), 21);
+    assert_eq!(root(), committed);
+  }
+}
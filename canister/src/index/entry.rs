@@ -77,6 +77,15 @@ pub struct RuneEntry {
   pub turbo: bool,
 }
 
+pub fn to_terms(terms: Terms) -> runes_indexer_interface::Terms {
+  runes_indexer_interface::Terms {
+    amount: terms.amount,
+    cap: terms.cap,
+    height: terms.height,
+    offset: terms.offset,
+  }
+}
+
 impl RuneEntry {
   pub fn mintable(&self, height: u64) -> Result<u128, MintError> {
     let Some(terms) = self.terms else {
@@ -122,6 +131,31 @@ impl RuneEntry {
           .unwrap_or_default()
   }
 
+  pub fn to_interface(
+    &self,
+    rune_id: RuneId,
+    confirmations: u32,
+    outpoints: u64,
+  ) -> runes_indexer_interface::RuneEntry {
+    runes_indexer_interface::RuneEntry {
+      confirmations,
+      rune_id: rune_id.to_string(),
+      block: self.block,
+      burned: self.burned,
+      divisibility: self.divisibility,
+      etching: self.etching.to_string(),
+      mints: self.mints,
+      number: self.number,
+      outpoints,
+      premine: self.premine,
+      spaced_rune: self.spaced_rune.to_string(),
+      symbol: self.symbol.map(|c| c.to_string()),
+      terms: self.terms.map(to_terms),
+      timestamp: self.timestamp,
+      turbo: self.turbo,
+    }
+  }
+
   pub fn pile(&self, amount: u128) -> Pile {
     Pile {
       amount,
@@ -359,6 +393,8 @@ impl Entry for SatPoint {
 
 pub(super) type TxidValue = [u8; 32];
 
+pub(super) type ScriptHashValue = [u8; 32];
+
 impl Entry for Txid {
   type Value = TxidValue;
 
@@ -395,6 +431,46 @@ impl Storable for RuneBalances {
   const BOUND: Bound = Bound::Unbounded;
 }
 
+/// Leading bytes of a versioned encoding. No encoding stored before versions were introduced
+/// starts with them: that of a `Config` starts with the index of its network and that of a
+/// `ChangeRecord` with a number of outpoints far below what these bytes read as.
+const VERSION_TAG: [u8; 3] = [0xff, b'R', b'I'];
+
+/// A stored struct encoded with bincode behind `VERSION_TAG` and the version of its layout. A
+/// struct that gains a field bumps `VERSION` and decodes the layouts before it explicitly, like
+/// `ChangeRecord` does for the one stored before versions were introduced.
+pub(crate) trait Versioned: Serialize + serde::de::DeserializeOwned {
+  const VERSION: u8;
+
+  fn to_versioned_bytes(&self) -> Vec<u8> {
+    let mut bytes = VERSION_TAG.to_vec();
+    bytes.push(Self::VERSION);
+    bincode::serialize_into(&mut bytes, self).unwrap();
+    bytes
+  }
+
+  /// Decodes the current layout, panics on any other.
+  fn from_versioned_bytes(bytes: &[u8]) -> Self {
+    match split_version(bytes) {
+      Some((version, payload)) if version == Self::VERSION => {
+        bincode::deserialize(payload).unwrap()
+      }
+      other => panic!(
+        "unsupported encoding of {}, version {:?}",
+        std::any::type_name::<Self>(),
+        other.map(|(version, _)| version)
+      ),
+    }
+  }
+}
+
+/// Layout version and payload of a versioned encoding, `None` for an encoding stored before
+/// versions were introduced.
+pub(crate) fn split_version(bytes: &[u8]) -> Option<(u8, &[u8])> {
+  let (version, payload) = bytes.strip_prefix(&VERSION_TAG)?.split_first()?;
+  Some((*version, payload))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChangeRecord {
   pub removed_outpoints: Vec<(OutPoint, RuneBalances, u32)>,
@@ -402,6 +478,7 @@ pub struct ChangeRecord {
   pub burned: HashMap<RuneId, u128>,
   pub mints: HashMap<RuneId, u128>,
   pub added_runes: Vec<(Rune, RuneId, Txid)>,
+  pub removed_script_hashes: Vec<(OutPoint, ScriptHashValue)>,
 }
 
 impl ChangeRecord {
@@ -412,19 +489,160 @@ impl ChangeRecord {
       burned: HashMap::new(),
       mints: HashMap::new(),
       added_runes: Vec::new(),
+      removed_script_hashes: Vec::new(),
     }
   }
 }
 
+/// Layout of `ChangeRecord` stored before versions were introduced.
+#[derive(Deserialize)]
+struct ChangeRecordV0 {
+  removed_outpoints: Vec<(OutPoint, RuneBalances, u32)>,
+  added_outpoints: Vec<OutPoint>,
+  burned: HashMap<RuneId, u128>,
+  mints: HashMap<RuneId, u128>,
+  added_runes: Vec<(Rune, RuneId, Txid)>,
+}
+
+impl Versioned for ChangeRecord {
+  const VERSION: u8 = 1;
+}
+
 impl Storable for ChangeRecord {
   fn to_bytes(&self) -> Cow<[u8]> {
-    let vec = bincode::serialize(self).unwrap();
-    Cow::Owned(vec)
+    Cow::Owned(self.to_versioned_bytes())
   }
 
   fn from_bytes(bytes: Cow<[u8]>) -> Self {
-    bincode::deserialize(&bytes).unwrap()
+    if split_version(&bytes).is_some() {
+      return Self::from_versioned_bytes(&bytes);
+    }
+    let record: ChangeRecordV0 = bincode::deserialize(&bytes).unwrap();
+    ChangeRecord {
+      removed_outpoints: record.removed_outpoints,
+      added_outpoints: record.added_outpoints,
+      burned: record.burned,
+      mints: record.mints,
+      added_runes: record.added_runes,
+      removed_script_hashes: Vec::new(),
+    }
+  }
+
+  const BOUND: Bound = Bound::Unbounded;
+}
+
+/// What the indexer did with the runes of a single transaction.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RuneTransaction {
+  pub height: u32,
+  pub tx_index: u32,
+  pub artifact: Option<Artifact>,
+  pub inputs: Vec<(OutPoint, RuneBalances)>,
+  pub minted: Option<(RuneId, u128)>,
+  pub etched: Option<(RuneId, Rune)>,
+  /// Output that received the unallocated runes, if they were not burned.
+  pub pointer: Option<u32>,
+  pub allocations: Vec<(u32, RuneBalances)>,
+  pub burned: Vec<RuneBalance>,
+}
+
+impl Versioned for RuneTransaction {
+  const VERSION: u8 = 1;
+}
+
+impl Storable for RuneTransaction {
+  fn to_bytes(&self) -> Cow<[u8]> {
+    Cow::Owned(self.to_versioned_bytes())
+  }
+
+  fn from_bytes(bytes: Cow<[u8]>) -> Self {
+    Self::from_versioned_bytes(&bytes)
   }
 
   const BOUND: Bound = Bound::Unbounded;
 }
+
+/// Rune activity of a block. `mints` counts the mints of each rune in the block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockRunesSummary {
+  pub block_hash: BlockHash,
+  pub timestamp: u32,
+  pub etched: Vec<RuneId>,
+  pub mints: Vec<(RuneId, u128)>,
+  pub burned: Vec<RuneBalance>,
+  pub transactions: u32,
+}
+
+impl Versioned for BlockRunesSummary {
+  const VERSION: u8 = 1;
+}
+
+impl Storable for BlockRunesSummary {
+  fn to_bytes(&self) -> Cow<[u8]> {
+    Cow::Owned(self.to_versioned_bytes())
+  }
+
+  fn from_bytes(bytes: Cow<[u8]>) -> Self {
+    Self::from_versioned_bytes(&bytes)
+  }
+
+  const BOUND: Bound = Bound::Unbounded;
+}
+
+/// A rune-bearing outpoint that has been spent and the transaction that spent it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpentOutPoint {
+  pub rune_balances: RuneBalances,
+  pub height: u32,
+  pub spent_height: u32,
+  pub spender: Txid,
+}
+
+impl Versioned for SpentOutPoint {
+  const VERSION: u8 = 1;
+}
+
+impl Storable for SpentOutPoint {
+  fn to_bytes(&self) -> Cow<[u8]> {
+    Cow::Owned(self.to_versioned_bytes())
+  }
+
+  fn from_bytes(bytes: Cow<[u8]>) -> Self {
+    Self::from_versioned_bytes(&bytes)
+  }
+
+  const BOUND: Bound = Bound::Unbounded;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn change_record_stored_before_versioning_is_decoded() {
+    let outpoint = OutPoint::null();
+    let balances = RuneBalances {
+      balances: Vec::new(),
+    };
+    let bytes = bincode::serialize(&(
+      vec![(outpoint, balances, 7u32)],
+      vec![outpoint],
+      HashMap::<RuneId, u128>::new(),
+      HashMap::<RuneId, u128>::new(),
+      Vec::<(Rune, RuneId, Txid)>::new(),
+    ))
+    .unwrap();
+    assert!(split_version(&bytes).is_none());
+
+    let mut record = ChangeRecord::from_bytes(Cow::Owned(bytes));
+    assert_eq!(record.removed_outpoints.len(), 1);
+    assert_eq!(record.added_outpoints, vec![outpoint]);
+    assert!(record.removed_script_hashes.is_empty());
+
+    record.removed_script_hashes.push((outpoint, [1; 32]));
+    let bytes = record.to_bytes();
+    assert_eq!(split_version(&bytes).map(|(version, _)| version), Some(1));
+    let record = ChangeRecord::from_bytes(bytes);
+    assert_eq!(record.removed_script_hashes, vec![(outpoint, [1; 32])]);
+  }
+}
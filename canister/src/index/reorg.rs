@@ -95,66 +95,19 @@ impl Reorg {
   }
 
   pub fn handle_reorg(height: u32, depth: u32) {
+    crate::metrics::observe_reorg(depth);
+
     log!(
       INFO,
       "rolling back state after reorg of depth {depth} at height {height}"
     );
 
     for h in (height - depth + 1..height).rev() {
-      log!(INFO, "rolling back change record at height {h}");
-      if let Some(change_record) = crate::index::mem_get_change_record(h) {
-        change_record
-          .removed_outpoints
-          .iter()
-          .for_each(|(outpoint, rune_balances, height)| {
-            crate::index::mem_insert_outpoint_to_rune_balances(
-              outpoint.store(),
-              rune_balances.clone(),
-            );
-            crate::index::mem_insert_outpoint_to_height(outpoint.store(), *height);
-          });
-        change_record.added_outpoints.iter().for_each(|outpoint| {
-          crate::index::mem_remove_outpoint_to_rune_balances(outpoint.store());
-          crate::index::mem_remove_outpoint_to_height(outpoint.store());
-        });
-        change_record.burned.iter().for_each(|(rune_id, amount)| {
-          let mut entry = crate::index::mem_get_rune_id_to_rune_entry(rune_id.store()).unwrap();
-          entry.burned = *amount;
-          crate::index::mem_insert_rune_id_to_rune_entry(rune_id.store(), entry);
-          log!(
-            INFO,
-            "resetting burned for rune_id: {} to {}",
-            rune_id,
-            amount
-          );
-        });
-        change_record.mints.iter().for_each(|(rune_id, amount)| {
-          let mut entry = crate::index::mem_get_rune_id_to_rune_entry(rune_id.store()).unwrap();
-          entry.mints = *amount;
-          crate::index::mem_insert_rune_id_to_rune_entry(rune_id.store(), entry);
-          log!(
-            INFO,
-            "resetting mints for rune_id: {} to {}",
-            rune_id,
-            amount
-          );
-        });
-        change_record
-          .added_runes
-          .iter()
-          .for_each(|(rune, rune_id, txid)| {
-            crate::index::mem_remove_rune_to_rune_id(rune.store());
-            crate::index::mem_remove_rune_id_to_rune_entry(rune_id.store());
-            crate::index::mem_remove_transaction_id_to_rune(txid.store());
-            log!(INFO, "removing rune_id: {}", rune_id);
-          });
-      }
-      crate::index::mem_remove_change_record(h);
-      crate::index::mem_remove_statistic_runes(h);
-      crate::index::mem_remove_statistic_reserved_runes(h);
-      crate::index::mem_remove_block_header(h);
+      Self::rollback(h);
     }
 
+    crate::index::certification::commit();
+
     log!(
       INFO,
       "successfully rolled back state to height {}",
@@ -162,6 +115,94 @@ impl Reorg {
     );
   }
 
+  /// Reverts the changes block `h` made to the index.
+  fn rollback(h: u32) {
+    log!(INFO, "rolling back change record at height {h}");
+    if let Some(change_record) = crate::index::mem_get_change_record(h) {
+      change_record
+        .removed_outpoints
+        .iter()
+        .for_each(|(outpoint, rune_balances, height)| {
+          crate::index::mem_insert_outpoint_to_rune_balances(
+            outpoint.store(),
+            rune_balances.clone(),
+          );
+          crate::index::mem_insert_outpoint_to_height(outpoint.store(), *height);
+        });
+      change_record
+        .removed_script_hashes
+        .iter()
+        .for_each(|(outpoint, script_hash)| {
+          let rune_balances =
+            crate::index::mem_get_outpoint_to_rune_balances(outpoint.store()).unwrap();
+          crate::index::mem_insert_outpoint_to_script_hash(outpoint.store(), *script_hash);
+          crate::index::mem_insert_script_hash_to_outpoint(
+            *script_hash,
+            outpoint.store(),
+            &rune_balances,
+          );
+        });
+      change_record.added_outpoints.iter().for_each(|outpoint| {
+        let rune_balances = crate::index::mem_remove_outpoint_to_rune_balances(outpoint.store());
+        crate::index::mem_remove_outpoint_to_height(outpoint.store());
+        if let Some((script_hash, rune_balances)) =
+          crate::index::mem_remove_outpoint_to_script_hash(outpoint.store()).zip(rune_balances)
+        {
+          crate::index::mem_remove_script_hash_to_outpoint(
+            script_hash,
+            outpoint.store(),
+            &rune_balances,
+          );
+        }
+      });
+      change_record
+        .mints
+        .keys()
+        .chain(change_record.burned.keys())
+        .for_each(|rune_id| crate::index::mem_remove_rune_id_height_to_supply(rune_id.store(), h));
+      change_record.burned.iter().for_each(|(rune_id, amount)| {
+        let mut entry = crate::index::mem_get_rune_id_to_rune_entry(rune_id.store()).unwrap();
+        entry.burned = *amount;
+        crate::index::mem_insert_rune_id_to_rune_entry(rune_id.store(), entry);
+        log!(
+          INFO,
+          "resetting burned for rune_id: {} to {}",
+          rune_id,
+          amount
+        );
+      });
+      change_record.mints.iter().for_each(|(rune_id, amount)| {
+        let mut entry = crate::index::mem_get_rune_id_to_rune_entry(rune_id.store()).unwrap();
+        entry.mints = *amount;
+        crate::index::mem_insert_rune_id_to_rune_entry(rune_id.store(), entry);
+        log!(
+          INFO,
+          "resetting mints for rune_id: {} to {}",
+          rune_id,
+          amount
+        );
+      });
+      change_record
+        .added_runes
+        .iter()
+        .for_each(|(rune, rune_id, txid)| {
+          crate::index::mem_remove_rune_to_rune_id(rune.store());
+          if let Some(entry) = crate::index::mem_remove_rune_id_to_rune_entry(rune_id.store()) {
+            crate::index::mem_remove_number_to_rune_id(entry.number);
+          }
+          crate::index::mem_remove_transaction_id_to_rune(txid.store());
+          log!(INFO, "removing rune_id: {}", rune_id);
+        });
+    }
+    crate::index::mem_remove_rune_transactions(h);
+    crate::index::mem_remove_spent_outpoints(h);
+    crate::index::mem_remove_block_runes_summary(h);
+    crate::index::mem_remove_change_record(h);
+    crate::index::mem_remove_statistic_runes(h);
+    crate::index::mem_remove_statistic_reserved_runes(h);
+    crate::index::mem_remove_block_header(h);
+  }
+
   pub(crate) fn prune_change_record(network: BitcoinNetwork, height: u32) {
     if height >= get_max_recoverable_reorg_depth(network) {
       let h = height - get_max_recoverable_reorg_depth(network);
@@ -173,3 +214,60 @@ impl Reorg {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::index::entry::ChangeRecord;
+  use crate::index::test_helpers::{insert_outpoint, rune_balances};
+  use bitcoin::{hashes::Hash, OutPoint, Txid};
+  use ordinals::RuneId;
+
+  const RUNE_ID: RuneId = RuneId { block: 1, tx: 0 };
+
+  fn outpoint(n: u8) -> OutPoint {
+    OutPoint {
+      txid: Txid::from_byte_array([n; 32]),
+      vout: 0,
+    }
+  }
+
+  #[test]
+  fn rollback_restores_holders() {
+    insert_outpoint(outpoint(1).store(), 100, &[(RUNE_ID.block, 10)]);
+
+    // block 101 splits the output in two
+    crate::index::mem_remove_outpoint_to_rune_balances(outpoint(1).store());
+    crate::index::mem_remove_outpoint_to_height(outpoint(1).store());
+    for n in [2, 3] {
+      insert_outpoint(outpoint(n).store(), 101, &[(RUNE_ID.block, 5)]);
+    }
+    let mut change_record = ChangeRecord::new();
+    change_record
+      .removed_outpoints
+      .push((outpoint(1), rune_balances(&[(RUNE_ID.block, 10)]), 100));
+    change_record
+      .added_outpoints
+      .extend([outpoint(2), outpoint(3)]);
+    crate::index::mem_insert_change_record(101, change_record);
+    assert_eq!(
+      crate::index::mem_get_rune_id_to_outpoint_count(RUNE_ID.store()),
+      2
+    );
+
+    Reorg::rollback(101);
+
+    assert_eq!(
+      crate::index::mem_get_rune_id_to_outpoint_count(RUNE_ID.store()),
+      1
+    );
+    assert_eq!(
+      crate::index::mem_get_rune_id_to_outpoints(RUNE_ID.store(), None, 10),
+      vec![(outpoint(1).store(), 10)]
+    );
+    assert_eq!(
+      crate::index::mem_get_outpoint_to_height(outpoint(1).store()),
+      Some(100)
+    );
+  }
+}
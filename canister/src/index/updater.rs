@@ -1,11 +1,18 @@
 use self::rune_updater::RuneUpdater;
 use super::*;
+use crate::index::entry::RuneTransaction;
 use crate::index::reorg::Reorg;
 use crate::logs::{CRITICAL, INFO};
 use crate::timestamp;
 use candid::Principal;
+use std::collections::HashSet;
 
+mod backoff;
+mod prefetch;
 mod rune_updater;
+mod watchdog;
+
+use self::backoff::Outcome;
 
 pub(crate) struct BlockData {
   pub(crate) header: Header,
@@ -28,107 +35,89 @@ impl From<Block> for BlockData {
   }
 }
 
+/// Outcome of running a transaction through the rune allocation logic without indexing it.
+pub struct Simulation {
+  pub transaction: RuneTransaction,
+  pub mint_error: Option<String>,
+  pub etching_error: Option<String>,
+}
+
+/// Simulates `tx` against the current rune state as if it were included in the next block, fails
+/// if `tx` spends an output more than once.
+pub fn simulate_transaction(network: BitcoinNetwork, tx: &Transaction) -> Result<Simulation> {
+  let (height, _) = crate::index::next_block(network);
+  rune_updater::simulate(
+    tx,
+    height,
+    Rune::minimum_at_height(crate::index::bitcoin_network(network), Height(height)),
+  )
+}
+
+/// Starts the index loop, replacing any loop already running.
 pub fn update_index(network: BitcoinNetwork, subscribers: Vec<Principal>) -> Result {
-  ic_cdk_timers::set_timer(std::time::Duration::from_secs(10), move || {
-    ic_cdk::spawn(async move {
-      let (height, index_prev_blockhash) = crate::index::next_block(network);
-      match crate::bitcoin_api::get_block_hash(network, height).await {
-        Ok(Some(block_hash)) => match crate::rpc::get_block(block_hash).await {
-          Ok(block) => {
-            match Reorg::detect_reorg(
-              network,
-              index_prev_blockhash,
-              block.header.prev_blockhash,
-              height,
-            )
-            .await
-            {
-              Ok(()) => {
-                let txids: Vec<String> = block
-                  .txdata
-                  .iter()
-                  .map(|(_, txid)| txid.to_string())
-                  .collect();
-                let block_timestamp = block.header.time as u64;
-                if let Err(e) = index_block(network, height, block).await {
-                  log!(
-                    CRITICAL,
-                    "failed to index_block at height {}: {:?}",
-                    height,
-                    e
-                  );
-                  return;
-                }
-                Reorg::prune_change_record(network, height);
-                for subscriber in subscribers.iter() {
-                  let _ = crate::notifier::notify_new_block(
-                    *subscriber,
-                    height,
-                    block_hash.to_string(),
-                    block_timestamp,
-                    txids.clone(),
-                  )
-                  .await;
-                  log!(
-                    INFO,
-                    "notified subscriber: {:?} with block_height: {:?} block_hash: {:?} block_timestamp: {:?}",
-                    subscriber.to_text(),
-                    height,
-                    block_hash,
-                    block_timestamp
-                  );
-                }
-              }
-              Err(e) => match e {
-                reorg::Error::Recoverable { height, depth } => {
-                  Reorg::handle_reorg(height, depth);
-                }
-                reorg::Error::Unrecoverable => {
-                  log!(
-                    CRITICAL,
-                    "unrecoverable reorg detected at height {}",
-                    height
-                  );
-                  return;
-                }
-                reorg::Error::Retry => {
-                  log!(INFO, "retry reorg detected at height {}", height);
-                }
-              },
-            }
-          }
-          Err(e) => {
-            log!(
-              CRITICAL,
-              "failed to get_block: {:?} error: {:?}",
-              block_hash,
-              e
-            );
-          }
-        },
-        Ok(None) => {}
-        Err(e) => {
-          let message = format!("failed to get_block_hash at height {}: {:?}", height, e);
-          let is_new_message = CRITICAL.with_borrow(|sink| {
-            sink
-              .iter()
-              .last()
-              .map_or(true, |entry| entry.message != message)
-          });
+  crate::index::set_halted(false);
+  let generation = watchdog::next_generation();
+  schedule_update(
+    network,
+    subscribers,
+    generation,
+    std::time::Duration::from_secs(10),
+  )
+}
 
-          if is_new_message {
-            log!(CRITICAL, "{}", message);
-          }
+/// Hands a loop whose iteration trapped over to the watchdog, which restarts it after a backoff.
+pub(crate) fn trapped() {
+  watchdog::trapped(ic_cdk::api::time());
+}
+
+fn schedule_update(
+  network: BitcoinNetwork,
+  subscribers: Vec<Principal>,
+  generation: u64,
+  delay: std::time::Duration,
+) -> Result {
+  crate::index::set_indexing(true);
+  ic_cdk_timers::set_timer(delay, move || {
+    ic_cdk::spawn(async move {
+      if !watchdog::is_current(generation) {
+        return;
+      }
+      watchdog::heartbeat();
+      let in_flight = crate::index::begin_iteration(generation);
+      let budget = crate::index::mem_get_config().get_instruction_budget();
+      let outcome = loop {
+        let outcome = index_next_block(network, &subscribers, generation).await;
+        // keep going while catching up, the counter covers every await of this tick
+        if outcome != Some(Outcome::Progress)
+          || lag() == Some(0)
+          || !watchdog::is_current(generation)
+          || is_shutting_down()
+          || ic_cdk::api::performance_counter(1) >= budget
+        {
+          break outcome;
         }
+        watchdog::heartbeat();
+      };
+      drop(in_flight);
+      // a restarted loop schedules itself, the stale one must not touch its flags
+      if !watchdog::is_current(generation) {
+        return;
       }
+      let Some(outcome) = outcome else {
+        crate::index::set_halted(true);
+        crate::index::set_indexing(false);
+        return;
+      };
       if is_shutting_down() {
         log!(
           INFO,
           "shutting down index thread, skipping update at height {}",
-          height
+          crate::index::next_block(network).0
         );
+        crate::index::set_indexing(false);
       } else {
-        let _ = update_index(network, subscribers);
+        let delay = backoff::next_delay(outcome, lag());
+        let _ = schedule_update(network, subscribers, generation, delay);
       }
     });
   });
@@ -136,7 +125,147 @@ pub fn update_index(network: BitcoinNetwork, subscribers: Vec<Principal>) -> Res
   Ok(())
 }
 
-async fn index_block(network: BitcoinNetwork, height: u32, block: BlockData) -> Result<()> {
+/// Blocks between the latest indexed block and the Bitcoin tip last seen.
+fn lag() -> Option<u32> {
+  let tip_height = crate::metrics::tip_height()?;
+  let height = crate::index::mem_latest_block_height()?;
+  Some(tip_height.saturating_sub(height))
+}
+
+/// Runs one iteration of the index loop, returns `None` if the loop has to stop.
+async fn index_next_block(
+  network: BitcoinNetwork,
+  subscribers: &[Principal],
+  generation: u64,
+) -> Option<Outcome> {
+  // blocks would change the indices while they are scanned
+  if !crate::index::backfill::is_done() {
+    return Some(Outcome::NoNewBlock);
+  }
+  let (height, index_prev_blockhash) = crate::index::next_block(network);
+  match crate::bitcoin_api::get_block_hash(network, height).await {
+    Ok(Some(block_hash)) => match prefetch::get_block(network, height, block_hash).await {
+      Ok(block) => {
+        match Reorg::detect_reorg(
+          network,
+          index_prev_blockhash,
+          block.header.prev_blockhash,
+          height,
+        )
+        .await
+        {
+          // a restarted loop owns the index now
+          _ if !watchdog::is_current(generation) => Some(Outcome::Progress),
+          Ok(()) => {
+            let txids: Vec<String> = block
+              .txdata
+              .iter()
+              .map(|(_, txid)| txid.to_string())
+              .collect();
+            let block_timestamp = block.header.time as u64;
+            match index_block(network, height, block).await {
+              Ok(true) => {}
+              // a restarted loop indexed it while this one awaited, which is no failure
+              Ok(false) => return Some(Outcome::Progress),
+              Err(e) => {
+                let message = format!("failed to index_block at height {}: {:?}", height, e);
+                log!(CRITICAL, "{}", message);
+                crate::metrics::observe_error(message);
+                return Some(Outcome::of_index(&e));
+              }
+            }
+            crate::metrics::observe_block_indexed();
+            watchdog::reset_backoff();
+            Reorg::prune_change_record(network, height);
+            prune_rune_transactions(height);
+            prune_spent_outpoints(height);
+            for subscriber in subscribers.iter() {
+              let _ = crate::notifier::notify_new_block(
+                *subscriber,
+                height,
+                block_hash.to_string(),
+                block_timestamp,
+                txids.clone(),
+              )
+              .await;
+              log!(
+                INFO,
+                "notified subscriber: {:?} with block_height: {:?} block_hash: {:?} block_timestamp: {:?}",
+                subscriber.to_text(),
+                height,
+                block_hash,
+                block_timestamp
+              );
+            }
+            Some(Outcome::Progress)
+          }
+          Err(e) => match e {
+            reorg::Error::Recoverable { height, depth } => {
+              Reorg::handle_reorg(height, depth);
+              prefetch::clear();
+              Some(Outcome::Progress)
+            }
+            reorg::Error::Unrecoverable => {
+              let message = format!("unrecoverable reorg detected at height {}", height);
+              log!(CRITICAL, "{}", message);
+              crate::metrics::observe_error(message);
+              None
+            }
+            reorg::Error::Retry => {
+              log!(INFO, "retry reorg detected at height {}", height);
+              Some(Outcome::Transient)
+            }
+          },
+        }
+      }
+      Err(e) => {
+        let message = format!("failed to get_block: {:?} error: {:?}", block_hash, e);
+        log!(CRITICAL, "{}", message);
+        crate::metrics::observe_error(message);
+        Some(Outcome::of_fetch(&e))
+      }
+    },
+    Ok(None) => Some(Outcome::NoNewBlock),
+    Err(e) => {
+      let message = format!("failed to get_block_hash at height {}: {:?}", height, e);
+      let is_new_message = CRITICAL.with_borrow(|sink| {
+        sink
+          .iter()
+          .last()
+          .map_or(true, |entry| entry.message != message)
+      });
+
+      if is_new_message {
+        log!(CRITICAL, "{}", message);
+      }
+      crate::metrics::observe_error(message);
+      Some(Outcome::of_fetch(&e))
+    }
+  }
+}
+
+fn prune_rune_transactions(height: u32) {
+  let retention = crate::index::mem_get_config().get_rune_transaction_retention();
+  if height >= retention {
+    crate::index::mem_prune_rune_transactions(height - retention);
+  }
+}
+
+fn prune_spent_outpoints(height: u32) {
+  let config = crate::index::mem_get_config();
+  // history mode answers queries from spent outpoints, so they are kept for good
+  if config.history_start.is_some() {
+    return;
+  }
+  let retention = config.get_spent_outpoint_retention();
+  if height >= retention {
+    crate::index::mem_prune_spent_outpoints(height - retention);
+  }
+}
+
+/// Returns whether the block was applied, `false` if it was indexed by another iteration while
+/// its commitments were resolved.
+async fn index_block(network: BitcoinNetwork, height: u32, block: BlockData) -> Result<bool> {
   log!(
     INFO,
     "Block {} at {} with {} transactions…",
@@ -145,6 +274,54 @@ async fn index_block(network: BitcoinNetwork, height: u32, block: BlockData) ->
     block.txdata.len()
   );
 
+  let minimum = Rune::minimum_at_height(crate::index::bitcoin_network(network), Height(height));
+
+  // everything the block needs from Bitcoin RPC is resolved before the index is touched
+  let commitments =
+    rune_updater::resolve_commitments(network, &block.txdata, height, minimum).await?;
+
+  if crate::index::next_block(network).0 != height {
+    log!(INFO, "block {} was indexed while resolving it", height);
+    return Ok(false);
+  }
+
+  // a block that cannot be applied fails here, before anything is written
+  validate_block(&block)?;
+
+  // applying doesn't await, so a trap rolls back every change made to the index since the
+  // commitments were resolved and a block is applied fully or not at all; after validation only
+  // a broken invariant of the index gets here
+  if let Err(e) = apply_block(height, block, minimum, commitments) {
+    ic_cdk::trap(&format!("failed to apply block {}: {:?}", height, e));
+  }
+
+  Ok(true)
+}
+
+/// Checks that every input spending runes has the height the balances are recorded with.
+fn validate_block(block: &BlockData) -> Result<()> {
+  for (tx, _) in block.txdata.iter() {
+    for input in tx.input.iter() {
+      let outpoint_value = input.previous_output.store();
+      if crate::index::mem_get_outpoint_to_rune_balances(outpoint_value).is_some()
+        && crate::index::mem_get_outpoint_to_height(outpoint_value).is_none()
+      {
+        return Err(anyhow!(
+          "Outpoint not found in outpoint_to_height: {:?}",
+          input.previous_output
+        ));
+      }
+    }
+  }
+  Ok(())
+}
+
+fn apply_block(
+  height: u32,
+  block: BlockData,
+  minimum: Rune,
+  commitments: HashSet<Txid>,
+) -> Result<()> {
   let runes = crate::index::mem_statistic_runes();
   let reserved_runes = crate::index::mem_statistic_reserved_runes();
 
@@ -168,30 +345,70 @@ async fn index_block(network: BitcoinNetwork, height: u32, block: BlockData) ->
   crate::index::mem_insert_statistic_runes(height, runes);
   crate::index::mem_insert_statistic_reserved_runes(height, reserved_runes);
 
-  let network = match network {
-    BitcoinNetwork::Mainnet => bitcoin::Network::Bitcoin,
-    BitcoinNetwork::Testnet => bitcoin::Network::Testnet4,
-    BitcoinNetwork::Regtest => bitcoin::Network::Regtest,
-  };
-
   let mut rune_updater = RuneUpdater {
     block_time: block.header.time,
     burned: HashMap::new(),
     height,
-    minimum: Rune::minimum_at_height(network, Height(height)),
+    minimum,
     runes,
+    transactions: 0,
     change_record: ChangeRecord::new(),
+    record_transactions: crate::index::mem_get_config().get_rune_transaction_retention() > 0,
+    record_history: crate::index::mem_get_config().history_start.is_some(),
+    record_spent_outpoints: crate::index::mem_get_config().records_spent_outpoints(),
+    commitments,
   };
 
   for (i, (tx, txid)) in block.txdata.iter().enumerate() {
-    rune_updater
-      .index_runes(u32::try_from(i).unwrap(), tx, *txid)
-      .await?;
+    rune_updater.index_runes(u32::try_from(i).unwrap(), tx, *txid)?;
   }
 
-  rune_updater.update()?;
+  rune_updater.update(block.header.block_hash())?;
 
   crate::index::mem_insert_block_header(height, block.header.store());
 
+  crate::index::certification::commit();
+
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bitcoin::{absolute::LockTime, transaction::Version, TxIn};
+
+  fn block(previous_output: OutPoint) -> BlockData {
+    let tx = Transaction {
+      version: Version::TWO,
+      lock_time: LockTime::ZERO,
+      input: vec![TxIn {
+        previous_output,
+        ..Default::default()
+      }],
+      output: vec![],
+    };
+    Block {
+      header: bitcoin::constants::genesis_block(bitcoin::Network::Bitcoin).header,
+      txdata: vec![tx],
+    }
+    .into()
+  }
+
+  #[test]
+  fn spending_balances_without_a_height_fails_validation() {
+    let outpoint = OutPoint {
+      txid: Txid::all_zeros(),
+      vout: 1,
+    };
+    assert!(validate_block(&block(outpoint)).is_ok());
+
+    crate::index::mem_insert_outpoint_to_rune_balances(
+      outpoint.store(),
+      RuneBalances { balances: vec![] },
+    );
+    assert!(validate_block(&block(outpoint)).is_err());
+
+    crate::index::mem_insert_outpoint_to_height(outpoint.store(), 1);
+    assert!(validate_block(&block(outpoint)).is_ok());
+  }
+}
@@ -0,0 +1,142 @@
+use super::*;
+use crate::rpc::OutcallError;
+use std::cell::Cell;
+use std::time::Duration;
+
+/// Blocks behind the Bitcoin tip from which the loop runs at the catch-up cadence.
+const CATCH_UP_LAG: u32 = 6;
+const CATCH_UP_DELAY: Duration = Duration::from_secs(1);
+const TIP_DELAY: Duration = Duration::from_secs(10);
+
+/// What an iteration of the index loop ran into, decides when the next one runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Outcome {
+  /// A block was indexed or a reorg rolled back.
+  Progress,
+  /// The next block is not available yet.
+  NoNewBlock,
+  /// An outcall failed or returned garbage, the next attempt may succeed.
+  Transient,
+  /// The replicas could not agree on an outcall response.
+  Consensus,
+  /// Indexing the block failed, retrying will most likely fail the same way.
+  Deterministic,
+}
+
+impl Outcome {
+  /// Classifies an error fetching data from Bitcoin.
+  pub(super) fn of_fetch(error: &anyhow::Error) -> Self {
+    match error.downcast_ref::<OutcallError>() {
+      Some(OutcallError::Consensus(_)) => Self::Consensus,
+      _ => Self::Transient,
+    }
+  }
+
+  /// Classifies an error indexing a block, which may come from an outcall made on the way.
+  pub(super) fn of_index(error: &anyhow::Error) -> Self {
+    if error.downcast_ref::<OutcallError>().is_some()
+      || error.downcast_ref::<serde_json::Error>().is_some()
+    {
+      Self::of_fetch(error)
+    } else {
+      Self::Deterministic
+    }
+  }
+
+  /// First delay and cap of the exponential backoff for repeated outcomes of this class.
+  fn policy(self) -> (Duration, Duration) {
+    match self {
+      Self::Progress => (TIP_DELAY, TIP_DELAY),
+      Self::NoNewBlock => (TIP_DELAY, Duration::from_secs(60)),
+      Self::Transient => (Duration::from_secs(10), Duration::from_secs(300)),
+      Self::Consensus => (Duration::from_secs(30), Duration::from_secs(300)),
+      Self::Deterministic => (Duration::from_secs(60), Duration::from_secs(300)),
+    }
+  }
+}
+
+thread_local! {
+  // the last outcome and how many times in a row it happened
+  static LAST: Cell<(Outcome, u32)> = const { Cell::new((Outcome::Progress, 0)) };
+}
+
+/// Returns how long to wait before the next iteration.
+///
+/// Caps stay below the watchdog stall timeout so a backing off loop is not taken for a stalled
+/// one.
+pub(super) fn next_delay(outcome: Outcome, lag: Option<u32>) -> Duration {
+  let repeats = LAST.with(|last| {
+    let (previous, repeats) = last.get();
+    let repeats = if previous == outcome { repeats + 1 } else { 0 };
+    last.set((outcome, repeats));
+    repeats
+  });
+
+  if outcome == Outcome::Progress && lag.is_some_and(|lag| lag >= CATCH_UP_LAG) {
+    return CATCH_UP_DELAY;
+  }
+
+  let (base, cap) = outcome.policy();
+  base.saturating_mul(1 << repeats.min(16)).min(cap)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn delays(outcome: Outcome, n: usize) -> Vec<u64> {
+    (0..n)
+      .map(|_| next_delay(outcome, None).as_secs())
+      .collect()
+  }
+
+  #[test]
+  fn repeated_outcomes_back_off_up_to_the_cap() {
+    assert_eq!(
+      delays(Outcome::Transient, 7),
+      [10, 20, 40, 80, 160, 300, 300]
+    );
+  }
+
+  #[test]
+  fn deterministic_failures_start_slower() {
+    assert_eq!(delays(Outcome::Deterministic, 5), [60, 120, 240, 300, 300]);
+  }
+
+  #[test]
+  fn catching_up_runs_at_the_catch_up_cadence() {
+    assert_eq!(
+      next_delay(Outcome::Progress, Some(CATCH_UP_LAG)),
+      CATCH_UP_DELAY
+    );
+    assert_eq!(
+      next_delay(Outcome::Progress, Some(CATCH_UP_LAG - 1)),
+      TIP_DELAY
+    );
+    assert_eq!(next_delay(Outcome::Progress, None), TIP_DELAY);
+    // failures back off even far behind the tip
+    assert_eq!(
+      next_delay(Outcome::Transient, Some(CATCH_UP_LAG)),
+      Duration::from_secs(10)
+    );
+  }
+
+  #[test]
+  fn another_outcome_resets_the_backoff() {
+    assert_eq!(delays(Outcome::Transient, 3), [10, 20, 40]);
+    assert_eq!(delays(Outcome::Progress, 2), [10, 10]);
+    assert_eq!(delays(Outcome::Transient, 2), [10, 20]);
+  }
+
+  #[test]
+  fn index_errors_are_classified_by_cause() {
+    let consensus = anyhow::Error::new(OutcallError::Consensus("differs".into()));
+    let status = anyhow::Error::new(OutcallError::Status(500u32.into()));
+    assert_eq!(Outcome::of_index(&consensus), Outcome::Consensus);
+    assert_eq!(Outcome::of_index(&status), Outcome::Transient);
+    assert_eq!(
+      Outcome::of_index(&anyhow!("rune balance overflow")),
+      Outcome::Deterministic
+    );
+  }
+}
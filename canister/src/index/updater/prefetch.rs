@@ -0,0 +1,162 @@
+use super::*;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+/// How many blocks past the one being indexed are fetched ahead.
+const PREFETCH_DEPTH: u32 = 4;
+
+enum Slot {
+  Fetching,
+  Ready(BlockHash, BlockData),
+}
+
+thread_local! {
+  static QUEUE: RefCell<BTreeMap<u32, Slot>> = const { RefCell::new(BTreeMap::new()) };
+  // bumped by `clear` so fetches started before a reorg are dropped when they land
+  static EPOCH: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Returns the block at `height`, from the queue if it was prefetched with the hash the Bitcoin
+/// API reports now, and starts fetching the blocks after it.
+pub(super) async fn get_block(
+  network: BitcoinNetwork,
+  height: u32,
+  block_hash: BlockHash,
+) -> Result<BlockData> {
+  let block = take(height, block_hash);
+  fill(network, height);
+  match block {
+    Some(block) => Ok(block),
+    None => crate::rpc::get_block(block_hash).await,
+  }
+}
+
+/// Drops every prefetched block, called when the chain reorganizes.
+pub(super) fn clear() {
+  EPOCH.set(EPOCH.get() + 1);
+  QUEUE.with_borrow_mut(|queue| queue.clear());
+}
+
+fn take(height: u32, block_hash: BlockHash) -> Option<BlockData> {
+  let slot = QUEUE.with_borrow_mut(|queue| queue.remove(&height));
+  match slot {
+    Some(Slot::Ready(hash, block)) if hash == block_hash => Some(block),
+    // the chain changed since the block was fetched, the rest of the queue is stale too
+    Some(Slot::Ready(..)) => {
+      clear();
+      None
+    }
+    // a fetch still in flight is dropped when it lands, since its slot is gone
+    Some(Slot::Fetching) | None => None,
+  }
+}
+
+/// Starts fetching the blocks after `height` up to the last seen tip that are not queued yet.
+fn fill(network: BitcoinNetwork, height: u32) {
+  QUEUE.with_borrow_mut(|queue| queue.retain(|queued, _| *queued > height));
+
+  let Some(tip_height) = crate::metrics::tip_height() else {
+    return;
+  };
+
+  let epoch = EPOCH.get();
+  for next in height + 1..=tip_height.min(height + PREFETCH_DEPTH) {
+    let queued = QUEUE.with_borrow_mut(|queue| {
+      if queue.contains_key(&next) {
+        return true;
+      }
+      queue.insert(next, Slot::Fetching);
+      false
+    });
+    if queued {
+      continue;
+    }
+
+    ic_cdk::spawn(async move {
+      land(epoch, next, fetch(network, next).await);
+    });
+  }
+}
+
+/// Queues a fetched block, unless the queue was cleared or its slot dropped since the fetch
+/// of `epoch` started.
+fn land(epoch: u64, height: u32, fetched: Result<Option<(BlockHash, BlockData)>>) {
+  if EPOCH.get() != epoch {
+    return;
+  }
+  QUEUE.with_borrow_mut(|queue| {
+    if !matches!(queue.get(&height), Some(Slot::Fetching)) {
+      return;
+    }
+    match fetched {
+      Ok(Some((block_hash, block))) => {
+        queue.insert(height, Slot::Ready(block_hash, block));
+      }
+      Ok(None) | Err(_) => {
+        queue.remove(&height);
+      }
+    }
+  });
+}
+
+async fn fetch(network: BitcoinNetwork, height: u32) -> Result<Option<(BlockHash, BlockData)>> {
+  let Some(block_hash) = crate::bitcoin_api::get_block_hash(network, height).await? else {
+    return Ok(None);
+  };
+  Ok(Some((block_hash, crate::rpc::get_block(block_hash).await?)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn genesis() -> (BlockHash, BlockData) {
+    let block = bitcoin::constants::genesis_block(bitcoin::Network::Bitcoin);
+    (block.block_hash(), block.into())
+  }
+
+  fn fetching(height: u32) {
+    QUEUE.with_borrow_mut(|queue| queue.insert(height, Slot::Fetching));
+  }
+
+  #[test]
+  fn fetches_started_before_a_clear_are_dropped() {
+    let (block_hash, _) = genesis();
+    fetching(1);
+    let stale = EPOCH.get();
+    clear();
+    // requested again after the reorg, before the stale fetch lands
+    fetching(1);
+
+    land(stale, 1, Ok(Some(genesis())));
+    assert!(take(1, block_hash).is_none());
+
+    fetching(1);
+    land(EPOCH.get(), 1, Ok(Some(genesis())));
+    assert!(take(1, block_hash).is_some());
+  }
+
+  #[test]
+  fn fetches_without_a_slot_are_dropped() {
+    let (block_hash, _) = genesis();
+    land(EPOCH.get(), 1, Ok(Some(genesis())));
+    assert!(take(1, block_hash).is_none());
+
+    fetching(2);
+    land(EPOCH.get(), 2, Err(anyhow!("timeout")));
+    assert!(QUEUE.with_borrow(|queue| queue.is_empty()));
+  }
+
+  #[test]
+  fn a_block_with_another_hash_clears_the_queue() {
+    let (block_hash, _) = genesis();
+    for height in 1..=3 {
+      fetching(height);
+      land(EPOCH.get(), height, Ok(Some(genesis())));
+    }
+
+    assert!(take(1, BlockHash::all_zeros()).is_none());
+    assert_eq!(EPOCH.get(), 1);
+    assert!(take(2, block_hash).is_none());
+  }
+}
@@ -1,7 +1,8 @@
 use super::*;
-use crate::index::entry::RuneBalance;
+use crate::index::entry::{BlockRunesSummary, RuneBalance, RuneTransaction, SpentOutPoint};
 use crate::into_usize::IntoUsize;
 use crate::logs::WARNING;
+use std::collections::HashSet;
 
 pub(super) struct RuneUpdater {
   pub(super) block_time: u32,
@@ -9,26 +10,435 @@ pub(super) struct RuneUpdater {
   pub(super) height: u32,
   pub(super) minimum: Rune,
   pub(super) runes: u64,
+  pub(super) transactions: u32,
   pub(super) change_record: ChangeRecord,
+  pub(super) record_transactions: bool,
+  pub(super) record_history: bool,
+  pub(super) record_spent_outpoints: bool,
+  /// Transactions of the block whose named etching is committed, see `resolve_commitments`.
+  pub(super) commitments: HashSet<Txid>,
+}
+
+pub(super) struct Allocation {
+  pub(super) allocated: Vec<HashMap<RuneId, Lot>>,
+  pub(super) burned: HashMap<RuneId, Lot>,
+  pub(super) pointer: Option<u32>,
+}
+
+/// Distributes the runes available to a transaction over its outputs without touching any
+/// state. `unallocated` holds the input balances plus anything minted, `etched` the rune the
+/// transaction etches, if any. Runes sent to OP_RETURN outputs are moved to `burned`.
+pub(super) fn allocate(
+  tx: &Transaction,
+  artifact: Option<&Artifact>,
+  mut unallocated: HashMap<RuneId, Lot>,
+  etched: Option<(RuneId, Rune)>,
+) -> Allocation {
+  let mut allocated: Vec<HashMap<RuneId, Lot>> = vec![HashMap::new(); tx.output.len()];
+
+  if let Some(Artifact::Runestone(runestone)) = artifact {
+    if let Some((id, ..)) = etched {
+      *unallocated.entry(id).or_default() += runestone.etching.unwrap().premine.unwrap_or_default();
+    }
+
+    for Edict { id, amount, output } in runestone.edicts.iter().copied() {
+      let amount = Lot(amount);
+
+      // edicts with output values greater than the number of outputs
+      // should never be produced by the edict parser
+      let output = usize::try_from(output).unwrap();
+      assert!(output <= tx.output.len());
+
+      let id = if id == RuneId::default() {
+        let Some((id, ..)) = etched else {
+          continue;
+        };
+
+        id
+      } else {
+        id
+      };
+
+      let Some(balance) = unallocated.get_mut(&id) else {
+        continue;
+      };
+
+      let mut allocate = |balance: &mut Lot, amount: Lot, output: usize| {
+        if amount > 0 {
+          *balance -= amount;
+          *allocated[output].entry(id).or_default() += amount;
+        }
+      };
+
+      if output == tx.output.len() {
+        // find non-OP_RETURN outputs
+        let destinations = tx
+          .output
+          .iter()
+          .enumerate()
+          .filter_map(|(output, tx_out)| (!tx_out.script_pubkey.is_op_return()).then_some(output))
+          .collect::<Vec<usize>>();
+
+        if !destinations.is_empty() {
+          if amount == 0 {
+            // if amount is zero, divide balance between eligible outputs
+            let amount = *balance / destinations.len() as u128;
+            let remainder = usize::try_from(*balance % destinations.len() as u128).unwrap();
+
+            for (i, output) in destinations.iter().enumerate() {
+              allocate(
+                balance,
+                if i < remainder { amount + 1 } else { amount },
+                *output,
+              );
+            }
+          } else {
+            // if amount is non-zero, distribute amount to eligible outputs
+            for output in destinations {
+              allocate(balance, amount.min(*balance), output);
+            }
+          }
+        }
+      } else {
+        // Get the allocatable amount
+        let amount = if amount == 0 {
+          *balance
+        } else {
+          amount.min(*balance)
+        };
+
+        allocate(balance, amount, output);
+      }
+    }
+  }
+
+  let mut burned: HashMap<RuneId, Lot> = HashMap::new();
+
+  let mut default_output = None;
+
+  if let Some(Artifact::Cenotaph(_)) = artifact {
+    for (id, balance) in unallocated {
+      *burned.entry(id).or_default() += balance;
+    }
+  } else {
+    let pointer = artifact
+      .map(|artifact| match artifact {
+        Artifact::Runestone(runestone) => runestone.pointer,
+        Artifact::Cenotaph(_) => unreachable!(),
+      })
+      .unwrap_or_default();
+
+    // assign all un-allocated runes to the default output, or the first non
+    // OP_RETURN output if there is no default
+    if let Some(vout) = pointer
+      .map(|pointer| pointer.into_usize())
+      .inspect(|&pointer| assert!(pointer < allocated.len()))
+      .or_else(|| {
+        tx.output
+          .iter()
+          .enumerate()
+          .find(|(_vout, tx_out)| !tx_out.script_pubkey.is_op_return())
+          .map(|(vout, _tx_out)| vout)
+      })
+    {
+      default_output = Some(u32::try_from(vout).unwrap());
+      for (id, balance) in unallocated {
+        if balance > 0 {
+          *allocated[vout].entry(id).or_default() += balance;
+        }
+      }
+    } else {
+      for (id, balance) in unallocated {
+        if balance > 0 {
+          *burned.entry(id).or_default() += balance;
+        }
+      }
+    }
+  }
+
+  // increment burned balances
+  for (vout, balances) in allocated.iter_mut().enumerate() {
+    if tx.output[vout].script_pubkey.is_op_return() {
+      for (id, balance) in balances.drain() {
+        *burned.entry(id).or_default() += balance;
+      }
+    }
+  }
+
+  Allocation {
+    allocated,
+    burned,
+    pointer: default_output,
+  }
+}
+
+fn rune_balances(balances: HashMap<RuneId, Lot>) -> RuneBalances {
+  RuneBalances {
+    balances: balances
+      .into_iter()
+      .map(|(id, balance)| RuneBalance {
+        rune_id: id,
+        balance: balance.n(),
+      })
+      .collect(),
+  }
+}
+
+/// Runs `allocate` for `tx` against the current state as if it were included in block `height`,
+/// without writing anything. Etching commitments are not checked, and runes etched by `tx` are
+/// given transaction index 0 since the real one is not known yet.
+/// Fails for a transaction spending an output more than once, whose balances would be counted
+/// twice and which Bitcoin rejects anyway.
+pub(super) fn simulate(tx: &Transaction, height: u32, minimum: Rune) -> Result<Simulation> {
+  let mut spent = HashSet::new();
+  if !tx
+    .input
+    .iter()
+    .all(|input| spent.insert(input.previous_output))
+  {
+    return Err(anyhow!("transaction spends an output more than once"));
+  }
+
+  let artifact = Runestone::decipher(tx);
+
+  let mut inputs = Vec::new();
+  let mut unallocated: HashMap<RuneId, Lot> = HashMap::new();
+  for input in &tx.input {
+    if let Some(rune_balances) =
+      crate::index::mem_get_outpoint_to_rune_balances(input.previous_output.store())
+    {
+      for rune_balance in rune_balances.balances.iter() {
+        *unallocated.entry(rune_balance.rune_id).or_default() += rune_balance.balance;
+      }
+      inputs.push((input.previous_output, rune_balances));
+    }
+  }
+
+  let mut minted = None;
+  let mut mint_error = None;
+  let mut etched = None;
+  let mut etching_error = None;
+
+  if let Some(artifact) = &artifact {
+    if let Some(id) = artifact.mint() {
+      match crate::index::mem_get_rune_id_to_rune_entry(id.store()) {
+        Some(rune_entry) => match rune_entry.mintable(height.into()) {
+          Ok(amount) => {
+            *unallocated.entry(id).or_default() += amount;
+            minted = Some((id, amount));
+          }
+          Err(e) => mint_error = Some(e.to_string()),
+        },
+        None => mint_error = Some(format!("rune {id} not found")),
+      }
+    }
+
+    let rune = match artifact {
+      Artifact::Runestone(runestone) => runestone.etching.map(|etching| etching.rune),
+      Artifact::Cenotaph(cenotaph) => cenotaph.etching.map(Some),
+    };
+
+    if let Some(rune) = rune {
+      let id = RuneId {
+        block: height.into(),
+        tx: 0,
+      };
+      match rune {
+        Some(rune) if rune < minimum => {
+          etching_error = Some(format!("rune {rune} is below the minimum {minimum}"))
+        }
+        Some(rune) if rune.is_reserved() => {
+          etching_error = Some(format!("rune {rune} is reserved"))
+        }
+        Some(rune) if crate::index::mem_get_rune_to_rune_id(rune.store()).is_some() => {
+          etching_error = Some(format!("rune {rune} is already etched"))
+        }
+        Some(rune) => etched = Some((id, rune)),
+        None => etched = Some((id, Rune::reserved(height.into(), 0))),
+      }
+    }
+  }
+
+  let Allocation {
+    allocated,
+    burned,
+    pointer,
+  } = allocate(tx, artifact.as_ref(), unallocated, etched);
+
+  Ok(Simulation {
+    transaction: RuneTransaction {
+      height,
+      tx_index: 0,
+      artifact,
+      inputs,
+      minted,
+      etched,
+      pointer,
+      allocations: allocated
+        .into_iter()
+        .enumerate()
+        .filter(|(_, balances)| !balances.is_empty())
+        .map(|(vout, balances)| (u32::try_from(vout).unwrap(), rune_balances(balances)))
+        .collect(),
+      burned: rune_balances(burned).balances,
+    },
+    mint_error,
+    etching_error,
+  })
+}
+
+/// Looks up the commitments of the named etchings in a block from Bitcoin RPC without touching
+/// the index, so that the block can then be applied without awaiting.
+pub(super) async fn resolve_commitments(
+  network: BitcoinNetwork,
+  txdata: &[(Transaction, Txid)],
+  height: u32,
+  minimum: Rune,
+) -> Result<HashSet<Txid>> {
+  let mut commitments = HashSet::new();
+
+  for (tx, txid) in txdata {
+    let rune = match Runestone::decipher(tx) {
+      Some(Artifact::Runestone(runestone)) => runestone.etching.and_then(|etching| etching.rune),
+      Some(Artifact::Cenotaph(cenotaph)) => cenotaph.etching,
+      None => None,
+    };
+
+    let Some(rune) = rune else {
+      continue;
+    };
+
+    // a rune etched before this block can't be etched again, whether an earlier transaction of
+    // this block etches it is checked when the block is applied
+    if rune < minimum
+      || rune.is_reserved()
+      || crate::index::mem_get_rune_to_rune_id(rune.store()).is_some()
+    {
+      continue;
+    }
+
+    if tx_commits_to_rune(network, tx, rune, height).await? {
+      commitments.insert(*txid);
+    }
+  }
+
+  Ok(commitments)
+}
+
+/// Height of the block including `txid`, proven by a merkle proof of the inclusion against a
+/// header whose hash the Bitcoin API reports at that height.
+async fn proven_height(network: BitcoinNetwork, txid: &Txid) -> Result<u32> {
+  let header = crate::rpc::get_tx_block_header(txid).await?;
+  let block_hash = header.block_hash();
+
+  // the height reported by RPC is only a claim until the Bitcoin API confirms the hash there
+  let height = crate::rpc::get_block_height(&block_hash).await?;
+
+  match crate::bitcoin_api::get_block_hash(network, height).await? {
+    Some(hash) if hash == block_hash => Ok(height),
+    hash => Err(anyhow!(
+      "block {} of transaction {} is not in the main chain at height {}, found {:?}",
+      block_hash,
+      txid,
+      height,
+      hash
+    )),
+  }
+}
+
+async fn tx_commits_to_rune(
+  network: BitcoinNetwork,
+  tx: &Transaction,
+  rune: Rune,
+  height: u32,
+) -> Result<bool> {
+  let commitment = rune.commitment();
+
+  for input in &tx.input {
+    // extracting a tapscript does not indicate that the input being spent
+    // was actually a taproot output. this is checked below, when we load the
+    // output's entry from the database
+    let Some(tapscript) = input.witness.tapscript() else {
+      continue;
+    };
+
+    for instruction in tapscript.instructions() {
+      // ignore errors, since the extracted script may not be valid
+      let Ok(instruction) = instruction else {
+        break;
+      };
+
+      let Some(pushbytes) = instruction.push_bytes() else {
+        continue;
+      };
+
+      if pushbytes.as_bytes() != commitment {
+        continue;
+      }
+
+      let commit_txid = input.previous_output.txid;
+      let commit_tx = crate::rpc::get_raw_transaction(&commit_txid).await?;
+
+      let taproot = commit_tx
+        .output
+        .get(input.previous_output.vout.into_usize())
+        .ok_or_else(|| {
+          anyhow!(
+            "Transaction {} has no output {}",
+            commit_txid,
+            input.previous_output.vout
+          )
+        })?
+        .script_pubkey
+        .is_p2tr();
+
+      if !taproot {
+        continue;
+      }
+
+      let commit_tx_height = proven_height(network, &commit_txid).await?;
+
+      match commitment_matured(commit_tx_height, height) {
+        Some(true) => return Ok(true),
+        Some(false) => {}
+        None => log!(
+          WARNING,
+          "Transaction {} at height {} spent at height {}",
+          commit_txid,
+          commit_tx_height,
+          height
+        ),
+      }
+    }
+  }
+
+  Ok(false)
+}
+
+/// Whether a commitment confirmed at `commit_height` is old enough for an etching at `height`,
+/// `None` if it was confirmed after it.
+fn commitment_matured(commit_height: u32, height: u32) -> Option<bool> {
+  let confirmations = height.checked_sub(commit_height)? + 1;
+  Some(confirmations >= Runestone::COMMIT_CONFIRMATIONS.into())
 }
 
 impl RuneUpdater {
-  pub(super) async fn index_runes(
-    &mut self,
-    tx_index: u32,
-    tx: &Transaction,
-    txid: Txid,
-  ) -> Result<()> {
+  pub(super) fn index_runes(&mut self, tx_index: u32, tx: &Transaction, txid: Txid) -> Result<()> {
     let artifact = Runestone::decipher(tx);
 
-    let mut unallocated = self.unallocated(tx)?;
+    let inputs_start = self.change_record.removed_outpoints.len();
+    let outputs_start = self.change_record.added_outpoints.len();
+
+    let mut unallocated = self.unallocated(tx, txid)?;
 
-    let mut allocated: Vec<HashMap<RuneId, Lot>> = vec![HashMap::new(); tx.output.len()];
+    let mut minted = None;
+    let mut etched = None;
 
     if let Some(artifact) = &artifact {
       if let Some(id) = artifact.mint() {
         if let Some(amount) = self.mint(id)? {
           *unallocated.entry(id).or_default() += amount;
+          minted = Some((id, amount.n()));
 
           // log!(
           //   INFO,
@@ -41,132 +451,20 @@ impl RuneUpdater {
         }
       }
 
-      let etched = self.etched(tx_index, tx, artifact).await?;
-
-      if let Artifact::Runestone(runestone) = artifact {
-        if let Some((id, ..)) = etched {
-          *unallocated.entry(id).or_default() +=
-            runestone.etching.unwrap().premine.unwrap_or_default();
-        }
-
-        for Edict { id, amount, output } in runestone.edicts.iter().copied() {
-          let amount = Lot(amount);
-
-          // edicts with output values greater than the number of outputs
-          // should never be produced by the edict parser
-          let output = usize::try_from(output).unwrap();
-          assert!(output <= tx.output.len());
-
-          let id = if id == RuneId::default() {
-            let Some((id, ..)) = etched else {
-              continue;
-            };
-
-            id
-          } else {
-            id
-          };
-
-          let Some(balance) = unallocated.get_mut(&id) else {
-            continue;
-          };
-
-          let mut allocate = |balance: &mut Lot, amount: Lot, output: usize| {
-            if amount > 0 {
-              *balance -= amount;
-              *allocated[output].entry(id).or_default() += amount;
-            }
-          };
-
-          if output == tx.output.len() {
-            // find non-OP_RETURN outputs
-            let destinations = tx
-              .output
-              .iter()
-              .enumerate()
-              .filter_map(|(output, tx_out)| {
-                (!tx_out.script_pubkey.is_op_return()).then_some(output)
-              })
-              .collect::<Vec<usize>>();
-
-            if !destinations.is_empty() {
-              if amount == 0 {
-                // if amount is zero, divide balance between eligible outputs
-                let amount = *balance / destinations.len() as u128;
-                let remainder = usize::try_from(*balance % destinations.len() as u128).unwrap();
-
-                for (i, output) in destinations.iter().enumerate() {
-                  allocate(
-                    balance,
-                    if i < remainder { amount + 1 } else { amount },
-                    *output,
-                  );
-                }
-              } else {
-                // if amount is non-zero, distribute amount to eligible outputs
-                for output in destinations {
-                  allocate(balance, amount.min(*balance), output);
-                }
-              }
-            }
-          } else {
-            // Get the allocatable amount
-            let amount = if amount == 0 {
-              *balance
-            } else {
-              amount.min(*balance)
-            };
-
-            allocate(balance, amount, output);
-          }
-        }
-      }
+      etched = self.etched(tx_index, txid, artifact)?;
 
       if let Some((id, rune)) = etched {
         self.create_rune_entry(txid, artifact, id, rune)?;
       }
     }
 
-    let mut burned: HashMap<RuneId, Lot> = HashMap::new();
+    let Allocation {
+      allocated,
+      burned,
+      pointer,
+    } = allocate(tx, artifact.as_ref(), unallocated, etched);
 
-    if let Some(Artifact::Cenotaph(_)) = artifact {
-      for (id, balance) in unallocated {
-        *burned.entry(id).or_default() += balance;
-      }
-    } else {
-      let pointer = artifact
-        .map(|artifact| match artifact {
-          Artifact::Runestone(runestone) => runestone.pointer,
-          Artifact::Cenotaph(_) => unreachable!(),
-        })
-        .unwrap_or_default();
-
-      // assign all un-allocated runes to the default output, or the first non
-      // OP_RETURN output if there is no default
-      if let Some(vout) = pointer
-        .map(|pointer| pointer.into_usize())
-        .inspect(|&pointer| assert!(pointer < allocated.len()))
-        .or_else(|| {
-          tx.output
-            .iter()
-            .enumerate()
-            .find(|(_vout, tx_out)| !tx_out.script_pubkey.is_op_return())
-            .map(|(vout, _tx_out)| vout)
-        })
-      {
-        for (id, balance) in unallocated {
-          if balance > 0 {
-            *allocated[vout].entry(id).or_default() += balance;
-          }
-        }
-      } else {
-        for (id, balance) in unallocated {
-          if balance > 0 {
-            *burned.entry(id).or_default() += balance;
-          }
-        }
-      }
-    }
+    let mut allocations = Vec::new();
 
     // update outpoint balances
     for (vout, balances) in allocated.into_iter().enumerate() {
@@ -174,14 +472,6 @@ impl RuneUpdater {
         continue;
       }
 
-      // increment burned balances
-      if tx.output[vout].script_pubkey.is_op_return() {
-        for (id, balance) in &balances {
-          *burned.entry(*id).or_default() += *balance;
-        }
-        continue;
-      }
-
       // let mut balances = balances.into_iter().collect::<Vec<(RuneId, Lot)>>();
 
       // Sort balances by id so tests can assert balances in a fixed order
@@ -192,22 +482,62 @@ impl RuneUpdater {
         vout: vout.try_into().unwrap(),
       };
 
-      let mut rune_balances = RuneBalances { balances: vec![] };
-
-      for (id, balance) in balances {
-        rune_balances.balances.push(RuneBalance {
-          rune_id: id,
-          balance: balance.n(),
-        });
+      let rune_balances = rune_balances(balances);
 
-        // log!(INFO, "Rune transferred: outpoint: {:?}, block_height: {}, txid: {:?}, rune_id: {:?}, amount: {:?}", outpoint, self.height, txid, id, balance.n());
+      if self.record_transactions {
+        allocations.push((outpoint.vout, rune_balances.clone()));
       }
+      let script_hash = crate::index::script_hash(&tx.output[vout].script_pubkey);
+      crate::index::mem_insert_outpoint_to_script_hash(outpoint.store(), script_hash);
+      crate::index::mem_insert_script_hash_to_outpoint(
+        script_hash,
+        outpoint.store(),
+        &rune_balances,
+      );
+
       crate::index::mem_insert_outpoint_to_rune_balances(outpoint.store(), rune_balances);
       crate::index::mem_insert_outpoint_to_height(outpoint.store(), self.height);
 
       self.change_record.added_outpoints.push(outpoint);
     }
 
+    if self.record_transactions {
+      let inputs = self.change_record.removed_outpoints[inputs_start..]
+        .iter()
+        .map(|(outpoint, rune_balances, _)| (*outpoint, rune_balances.clone()))
+        .collect::<Vec<_>>();
+
+      if artifact.is_some() || !inputs.is_empty() {
+        crate::index::mem_insert_rune_transaction(
+          txid,
+          RuneTransaction {
+            height: self.height,
+            tx_index,
+            artifact,
+            inputs,
+            minted,
+            etched,
+            pointer,
+            allocations,
+            burned: burned
+              .iter()
+              .map(|(id, amount)| RuneBalance {
+                rune_id: *id,
+                balance: amount.n(),
+              })
+              .collect(),
+          },
+        );
+      }
+    }
+
+    if self.change_record.removed_outpoints.len() > inputs_start
+      || self.change_record.added_outpoints.len() > outputs_start
+      || !burned.is_empty()
+    {
+      self.transactions += 1;
+    }
+
     // increment entries with burned runes
     for (id, amount) in burned {
       *self.burned.entry(id).or_default() += amount;
@@ -225,8 +555,15 @@ impl RuneUpdater {
     Ok(())
   }
 
-  pub(super) fn update(mut self) -> Result {
+  pub(super) fn update(mut self, block_hash: BlockHash) -> Result {
+    let mut burned_summary = Vec::new();
+
     for (rune_id, burned) in self.burned {
+      burned_summary.push(RuneBalance {
+        rune_id,
+        balance: burned.n(),
+      });
+
       let mut entry = crate::index::mem_get_rune_id_to_rune_entry(rune_id.store()).unwrap();
 
       if !self.change_record.burned.contains_key(&rune_id) {
@@ -237,6 +574,65 @@ impl RuneUpdater {
       crate::index::mem_insert_rune_id_to_rune_entry(rune_id.store(), entry);
     }
 
+    if self.record_history {
+      let rune_ids = self
+        .change_record
+        .mints
+        .keys()
+        .chain(self.change_record.burned.keys())
+        .copied()
+        .collect::<HashSet<RuneId>>();
+
+      for rune_id in rune_ids {
+        let entry = crate::index::mem_get_rune_id_to_rune_entry(rune_id.store()).unwrap();
+        crate::index::mem_insert_rune_id_height_to_supply(
+          rune_id.store(),
+          self.height,
+          self
+            .change_record
+            .mints
+            .get(&rune_id)
+            .copied()
+            .unwrap_or(entry.mints),
+          self
+            .change_record
+            .burned
+            .get(&rune_id)
+            .copied()
+            .unwrap_or(entry.burned),
+        );
+      }
+    }
+
+    let mut mints = self
+      .change_record
+      .mints
+      .iter()
+      .map(|(rune_id, mints_before)| {
+        let entry = crate::index::mem_get_rune_id_to_rune_entry(rune_id.store()).unwrap();
+        (*rune_id, entry.mints - mints_before)
+      })
+      .collect::<Vec<(RuneId, u128)>>();
+    mints.sort();
+    burned_summary.sort_by_key(|rune_balance| rune_balance.rune_id);
+
+    crate::index::mem_insert_block_runes_summary(
+      self.height,
+      BlockRunesSummary {
+        block_hash,
+        timestamp: self.block_time,
+        etched: self
+          .change_record
+          .added_runes
+          .iter()
+          .map(|(_, rune_id, _)| *rune_id)
+          .collect(),
+        mints,
+        burned: burned_summary,
+        transactions: self.transactions,
+      },
+    );
+
     crate::index::mem_insert_change_record(self.height, self.change_record);
 
     Ok(())
@@ -304,6 +700,7 @@ impl RuneUpdater {
     };
 
     crate::index::mem_insert_rune_id_to_rune_entry(id.store(), entry);
+    crate::index::mem_insert_number_to_rune_id(number, id.store());
 
     self.change_record.added_runes.push((rune, id, txid));
 
@@ -318,10 +715,10 @@ impl RuneUpdater {
     Ok(())
   }
 
-  async fn etched(
+  fn etched(
     &mut self,
     tx_index: u32,
-    tx: &Transaction,
+    txid: Txid,
     artifact: &Artifact,
   ) -> Result<Option<(RuneId, Rune)>> {
     let rune = match artifact {
@@ -339,7 +736,7 @@ impl RuneUpdater {
       if rune < self.minimum
         || rune.is_reserved()
         || crate::index::mem_get_rune_to_rune_id(rune.store()).is_some()
-        || !self.tx_commits_to_rune(tx, rune).await?
+        || !self.commitments.contains(&txid)
       {
         return Ok(None);
       }
@@ -381,66 +778,7 @@ impl RuneUpdater {
     Ok(Some(Lot(amount)))
   }
 
-  async fn tx_commits_to_rune(&self, tx: &Transaction, rune: Rune) -> Result<bool> {
-    let commitment = rune.commitment();
-
-    for input in &tx.input {
-      // extracting a tapscript does not indicate that the input being spent
-      // was actually a taproot output. this is checked below, when we load the
-      // output's entry from the database
-      let Some(tapscript) = input.witness.tapscript() else {
-        continue;
-      };
-
-      for instruction in tapscript.instructions() {
-        // ignore errors, since the extracted script may not be valid
-        let Ok(instruction) = instruction else {
-          break;
-        };
-
-        let Some(pushbytes) = instruction.push_bytes() else {
-          continue;
-        };
-
-        if pushbytes.as_bytes() != commitment {
-          continue;
-        }
-
-        let tx_info =
-          crate::rpc::get_raw_transaction_info(&input.previous_output.txid, None).await?;
-
-        let taproot = tx_info.vout[input.previous_output.vout.into_usize()]
-          .script_pub_key
-          .script()?
-          .is_p2tr();
-
-        if !taproot {
-          continue;
-        }
-
-        let block_hash = tx_info.blockhash.ok_or_else(|| {
-          log!(WARNING, "Transaction {} has no block hash", tx_info.txid);
-          anyhow!("Transaction {} has no block hash", tx_info.txid)
-        })?;
-
-        let commit_tx_height = crate::rpc::get_block_header_info(&block_hash).await?.height;
-
-        let confirmations = self
-          .height
-          .checked_sub(commit_tx_height.try_into().unwrap())
-          .unwrap()
-          + 1;
-
-        if confirmations >= Runestone::COMMIT_CONFIRMATIONS as u32 {
-          return Ok(true);
-        }
-      }
-    }
-
-    Ok(false)
-  }
-
-  fn unallocated(&mut self, tx: &Transaction) -> Result<HashMap<RuneId, Lot>> {
+  fn unallocated(&mut self, tx: &Transaction, txid: Txid) -> Result<HashMap<RuneId, Lot>> {
     // map of rune ID to un-allocated balance of that rune
     let mut unallocated: HashMap<RuneId, Lot> = HashMap::new();
 
@@ -460,6 +798,32 @@ impl RuneUpdater {
             )
           })?;
 
+        if self.record_spent_outpoints {
+          crate::index::mem_insert_outpoint_to_spent_outpoint(
+            input.previous_output.store(),
+            SpentOutPoint {
+              rune_balances: rune_balances.clone(),
+              height,
+              spent_height: self.height,
+              spender: txid,
+            },
+          );
+        }
+
+        if let Some(script_hash) =
+          crate::index::mem_remove_outpoint_to_script_hash(input.previous_output.store())
+        {
+          crate::index::mem_remove_script_hash_to_outpoint(
+            script_hash,
+            input.previous_output.store(),
+            &rune_balances,
+          );
+          self
+            .change_record
+            .removed_script_hashes
+            .push((input.previous_output, script_hash));
+        }
+
         self
           .change_record
           .removed_outpoints
@@ -470,3 +834,451 @@ impl RuneUpdater {
     Ok(unallocated)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::index::test_helpers::insert_outpoint;
+  use bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, ScriptBuf, Sequence, TxIn, TxOut, Witness,
+  };
+
+  const RUNE_ID: RuneId = RuneId { block: 1, tx: 0 };
+
+  fn updater(height: u32) -> RuneUpdater {
+    RuneUpdater {
+      block_time: 0,
+      burned: HashMap::new(),
+      height,
+      minimum: Rune(0),
+      runes: 0,
+      transactions: 0,
+      change_record: ChangeRecord::new(),
+      record_transactions: true,
+      record_history: false,
+      record_spent_outpoints: false,
+      commitments: HashSet::new(),
+    }
+  }
+
+  /// A transaction spending `inputs` to `outputs` plain outputs, followed by the runestone.
+  fn transaction(inputs: &[OutPoint], outputs: usize, runestone: Option<Runestone>) -> Transaction {
+    Transaction {
+      version: Version::TWO,
+      lock_time: LockTime::ZERO,
+      input: inputs
+        .iter()
+        .map(|previous_output| TxIn {
+          previous_output: *previous_output,
+          script_sig: ScriptBuf::new(),
+          sequence: Sequence::MAX,
+          witness: Witness::new(),
+        })
+        .collect(),
+      output: (0..outputs)
+        .map(|_| TxOut {
+          value: Amount::from_sat(1_000),
+          script_pubkey: ScriptBuf::new(),
+        })
+        .chain(runestone.map(|runestone| TxOut {
+          value: Amount::ZERO,
+          script_pubkey: runestone.encipher(),
+        }))
+        .collect(),
+    }
+  }
+
+  fn balance(rune_balances: &RuneBalances) -> Vec<(RuneId, u128)> {
+    let mut balances = rune_balances
+      .balances
+      .iter()
+      .map(|rune_balance| (rune_balance.rune_id, rune_balance.balance))
+      .collect::<Vec<_>>();
+    balances.sort();
+    balances
+  }
+
+  #[test]
+  fn commitments_need_enough_confirmations() {
+    assert_eq!(commitment_matured(100, 104), Some(false));
+    assert_eq!(commitment_matured(100, 105), Some(true));
+    assert_eq!(commitment_matured(100, 100), Some(false));
+    assert_eq!(commitment_matured(101, 100), None);
+  }
+
+  #[test]
+  fn transfer_is_recorded() {
+    let input = OutPoint {
+      txid: Txid::from_byte_array([1; 32]),
+      vout: 0,
+    };
+    insert_outpoint(input.store(), 10, &[(RUNE_ID.block, 100)]);
+    let tx = transaction(
+      &[input],
+      2,
+      Some(Runestone {
+        edicts: vec![Edict {
+          id: RUNE_ID,
+          amount: 30,
+          output: 1,
+        }],
+        ..Default::default()
+      }),
+    );
+    let txid = tx.compute_txid();
+
+    let mut updater = updater(20);
+    updater.index_runes(3, &tx, txid).unwrap();
+
+    let recorded = crate::index::mem_get_rune_transaction(txid).unwrap();
+    assert_eq!(recorded.height, 20);
+    assert_eq!(recorded.tx_index, 3);
+    assert_eq!(recorded.inputs.len(), 1);
+    assert_eq!(recorded.inputs[0].0, input);
+    assert_eq!(balance(&recorded.inputs[0].1), [(RUNE_ID, 100)]);
+    assert_eq!(recorded.pointer, Some(0));
+    assert_eq!(
+      recorded
+        .allocations
+        .iter()
+        .map(|(vout, rune_balances)| (*vout, balance(rune_balances)))
+        .collect::<Vec<_>>(),
+      [(0, vec![(RUNE_ID, 70)]), (1, vec![(RUNE_ID, 30)])]
+    );
+    assert!(recorded.burned.is_empty());
+    assert_eq!(updater.transactions, 1);
+  }
+
+  #[test]
+  fn transactions_without_runes_are_not_recorded() {
+    let tx = transaction(
+      &[OutPoint {
+        txid: Txid::from_byte_array([1; 32]),
+        vout: 0,
+      }],
+      1,
+      None,
+    );
+    let txid = tx.compute_txid();
+
+    let mut updater = updater(20);
+    updater.index_runes(0, &tx, txid).unwrap();
+
+    assert!(crate::index::mem_get_rune_transaction(txid).is_none());
+    assert_eq!(updater.transactions, 0);
+  }
+
+  #[test]
+  fn recording_can_be_disabled() {
+    let input = OutPoint {
+      txid: Txid::from_byte_array([1; 32]),
+      vout: 0,
+    };
+    insert_outpoint(input.store(), 10, &[(RUNE_ID.block, 100)]);
+    let tx = transaction(&[input], 1, None);
+    let txid = tx.compute_txid();
+
+    let mut updater = updater(20);
+    updater.record_transactions = false;
+    updater.index_runes(0, &tx, txid).unwrap();
+
+    assert!(crate::index::mem_get_rune_transaction(txid).is_none());
+    assert_eq!(
+      crate::index::mem_get_outpoint_to_rune_balances(OutPoint { txid, vout: 0 }.store())
+        .map(|rune_balances| balance(&rune_balances)),
+      Some(vec![(RUNE_ID, 100)])
+    );
+  }
+
+  fn lots(balances: &[(RuneId, u128)]) -> HashMap<RuneId, Lot> {
+    balances.iter().map(|(id, n)| (*id, Lot(*n))).collect()
+  }
+
+  fn amounts(balances: &HashMap<RuneId, Lot>) -> Vec<(RuneId, u128)> {
+    let mut amounts = balances
+      .iter()
+      .map(|(id, lot)| (*id, lot.n()))
+      .collect::<Vec<_>>();
+    amounts.sort();
+    amounts
+  }
+
+  #[test]
+  fn allocate_without_artifact_goes_to_first_output() {
+    let tx = transaction(&[], 2, None);
+
+    let allocation = allocate(&tx, None, lots(&[(RUNE_ID, 100)]), None);
+
+    assert_eq!(amounts(&allocation.allocated[0]), [(RUNE_ID, 100)]);
+    assert!(allocation.allocated[1].is_empty());
+    assert!(allocation.burned.is_empty());
+    assert_eq!(allocation.pointer, Some(0));
+  }
+
+  #[test]
+  fn allocate_splits_edict_to_all_outputs() {
+    let runestone = Runestone {
+      edicts: vec![Edict {
+        id: RUNE_ID,
+        amount: 0,
+        output: 3,
+      }],
+      ..Default::default()
+    };
+    let tx = transaction(&[], 2, Some(runestone));
+    let artifact = Runestone::decipher(&tx).unwrap();
+
+    let allocation = allocate(&tx, Some(&artifact), lots(&[(RUNE_ID, 11)]), None);
+
+    // the remainder goes to the first outputs, the OP_RETURN output gets nothing
+    assert_eq!(amounts(&allocation.allocated[0]), [(RUNE_ID, 6)]);
+    assert_eq!(amounts(&allocation.allocated[1]), [(RUNE_ID, 5)]);
+    assert!(allocation.allocated[2].is_empty());
+    assert!(allocation.burned.is_empty());
+  }
+
+  #[test]
+  fn allocate_burns_edicts_to_op_return() {
+    let runestone = Runestone {
+      edicts: vec![Edict {
+        id: RUNE_ID,
+        amount: 40,
+        output: 1,
+      }],
+      pointer: Some(0),
+      ..Default::default()
+    };
+    let tx = transaction(&[], 1, Some(runestone));
+    let artifact = Runestone::decipher(&tx).unwrap();
+
+    let allocation = allocate(&tx, Some(&artifact), lots(&[(RUNE_ID, 100)]), None);
+
+    assert_eq!(amounts(&allocation.allocated[0]), [(RUNE_ID, 60)]);
+    assert_eq!(amounts(&allocation.burned), [(RUNE_ID, 40)]);
+    assert_eq!(allocation.pointer, Some(0));
+  }
+
+  #[test]
+  fn allocate_burns_everything_for_cenotaph() {
+    // an edict to an output the transaction does not have makes the runestone a cenotaph
+    let tx = transaction(
+      &[],
+      1,
+      Some(Runestone {
+        edicts: vec![Edict {
+          id: RUNE_ID,
+          amount: 1,
+          output: 5,
+        }],
+        ..Default::default()
+      }),
+    );
+    let artifact = Runestone::decipher(&tx).unwrap();
+    assert!(matches!(artifact, Artifact::Cenotaph(_)));
+
+    let allocation = allocate(&tx, Some(&artifact), lots(&[(RUNE_ID, 100)]), None);
+
+    assert!(allocation.allocated.iter().all(HashMap::is_empty));
+    assert_eq!(amounts(&allocation.burned), [(RUNE_ID, 100)]);
+    assert_eq!(allocation.pointer, None);
+  }
+
+  #[test]
+  fn allocate_adds_premine_of_etched_rune() {
+    let etched = RuneId { block: 20, tx: 1 };
+    let runestone = Runestone {
+      etching: Some(Etching {
+        premine: Some(1_000),
+        ..Default::default()
+      }),
+      edicts: vec![Edict {
+        id: RuneId::default(),
+        amount: 250,
+        output: 1,
+      }],
+      ..Default::default()
+    };
+    let tx = transaction(&[], 2, Some(runestone));
+    let artifact = Runestone::decipher(&tx).unwrap();
+
+    let allocation = allocate(
+      &tx,
+      Some(&artifact),
+      HashMap::new(),
+      Some((etched, Rune(1))),
+    );
+
+    assert_eq!(amounts(&allocation.allocated[0]), [(etched, 750)]);
+    assert_eq!(amounts(&allocation.allocated[1]), [(etched, 250)]);
+  }
+
+  #[test]
+  fn simulate_does_not_touch_the_index() {
+    let input = OutPoint {
+      txid: Txid::from_byte_array([1; 32]),
+      vout: 0,
+    };
+    insert_outpoint(input.store(), 10, &[(RUNE_ID.block, 100)]);
+    let tx = transaction(
+      &[input],
+      2,
+      Some(Runestone {
+        edicts: vec![Edict {
+          id: RUNE_ID,
+          amount: 30,
+          output: 1,
+        }],
+        ..Default::default()
+      }),
+    );
+
+    let simulation = simulate(&tx, 20, Rune(0)).unwrap();
+
+    assert!(simulation.mint_error.is_none());
+    assert!(simulation.etching_error.is_none());
+    assert_eq!(simulation.transaction.inputs.len(), 1);
+    assert_eq!(
+      simulation
+        .transaction
+        .allocations
+        .iter()
+        .map(|(vout, rune_balances)| (*vout, balance(rune_balances)))
+        .collect::<Vec<_>>(),
+      [(0, vec![(RUNE_ID, 70)]), (1, vec![(RUNE_ID, 30)])]
+    );
+    assert!(crate::index::mem_get_outpoint_to_rune_balances(input.store()).is_some());
+    assert!(crate::index::mem_get_outpoint_to_height(input.store()).is_some());
+    assert!(crate::index::mem_get_rune_transaction(tx.compute_txid()).is_none());
+  }
+
+  #[test]
+  fn simulate_reports_mint_and_etching_errors() {
+    let tx = transaction(
+      &[],
+      1,
+      Some(Runestone {
+        mint: Some(RuneId { block: 5, tx: 0 }),
+        etching: Some(Etching {
+          rune: Some(Rune(0)),
+          ..Default::default()
+        }),
+        ..Default::default()
+      }),
+    );
+
+    let simulation = simulate(&tx, 20, Rune(1)).unwrap();
+
+    assert_eq!(simulation.mint_error.as_deref(), Some("rune 5:0 not found"));
+    assert_eq!(
+      simulation.etching_error,
+      Some(format!("rune {} is below the minimum {}", Rune(0), Rune(1)))
+    );
+    assert!(simulation.transaction.minted.is_none());
+    assert!(simulation.transaction.etched.is_none());
+  }
+
+  #[test]
+  fn simulate_rejects_duplicate_inputs() {
+    let input = OutPoint {
+      txid: Txid::from_byte_array([1; 32]),
+      vout: 0,
+    };
+    insert_outpoint(input.store(), 10, &[(RUNE_ID.block, 100)]);
+
+    assert!(simulate(&transaction(&[input, input], 1, None), 20, Rune(0)).is_err());
+  }
+
+  #[test]
+  fn block_summary_counts_mints_burns_and_transactions() {
+    crate::index::mem_insert_rune_id_to_rune_entry(
+      RUNE_ID.store(),
+      RuneEntry {
+        block: RUNE_ID.block,
+        burned: 0,
+        divisibility: 0,
+        etching: Txid::all_zeros(),
+        mints: 0,
+        number: 0,
+        premine: 0,
+        spaced_rune: SpacedRune {
+          rune: Rune(1_000_000),
+          spacers: 0,
+        },
+        symbol: None,
+        terms: Some(Terms {
+          amount: Some(10),
+          cap: Some(5),
+          height: (None, None),
+          offset: (None, None),
+        }),
+        timestamp: 0,
+        turbo: false,
+      },
+    );
+    let mut updater = updater(20);
+
+    let mint = transaction(
+      &[],
+      1,
+      Some(Runestone {
+        mint: Some(RUNE_ID),
+        ..Default::default()
+      }),
+    );
+    updater.index_runes(1, &mint, mint.compute_txid()).unwrap();
+
+    // spends the minted output and burns part of it in the OP_RETURN output
+    let burn = transaction(
+      &[OutPoint {
+        txid: mint.compute_txid(),
+        vout: 0,
+      }],
+      1,
+      Some(Runestone {
+        edicts: vec![Edict {
+          id: RUNE_ID,
+          amount: 4,
+          output: 1,
+        }],
+        ..Default::default()
+      }),
+    );
+    updater.index_runes(2, &burn, burn.compute_txid()).unwrap();
+
+    // neither spends nor creates runes
+    let plain = transaction(&[], 1, None);
+    updater
+      .index_runes(3, &plain, plain.compute_txid())
+      .unwrap();
+
+    updater.update(BlockHash::all_zeros()).unwrap();
+
+    let summary = crate::index::mem_get_block_runes_summary(20).unwrap();
+    assert_eq!(summary.mints, [(RUNE_ID, 1)]);
+    assert_eq!(
+      summary
+        .burned
+        .iter()
+        .map(|rune_balance| (rune_balance.rune_id, rune_balance.balance))
+        .collect::<Vec<_>>(),
+      [(RUNE_ID, 4)]
+    );
+    assert!(summary.etched.is_empty());
+    assert_eq!(summary.transactions, 2);
+
+    let entry = crate::index::mem_get_rune_id_to_rune_entry(RUNE_ID.store()).unwrap();
+    assert_eq!(entry.mints, 1);
+    assert_eq!(entry.burned, 4);
+    assert_eq!(
+      crate::index::mem_get_outpoint_to_rune_balances(
+        OutPoint {
+          txid: burn.compute_txid(),
+          vout: 0,
+        }
+        .store()
+      )
+      .map(|rune_balances| balance(&rune_balances)),
+      Some(vec![(RUNE_ID, 6)])
+    );
+  }
+}
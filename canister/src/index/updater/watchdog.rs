@@ -0,0 +1,125 @@
+use super::*;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64};
+use std::time::Duration;
+
+/// How often the watchdog checks the index loop.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(60);
+/// A loop without a heartbeat for this long is considered stalled.
+const STALL_TIMEOUT: Duration = Duration::from_secs(600);
+/// Consecutive restarts double the stall timeout, up to `STALL_TIMEOUT << MAX_BACKOFF_SHIFT`.
+const MAX_BACKOFF_SHIFT: u32 = 5;
+
+// incremented every time the loop is (re)started, iterations of an older generation stop
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static HEARTBEAT: AtomicU64 = AtomicU64::new(0);
+static RESTARTS: AtomicU32 = AtomicU32::new(0);
+// set when an iteration trapped, until the loop is started again
+static TRAPPED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+  static WATCHDOG: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Starts a new generation of the index loop and arms the watchdog if needed.
+pub(super) fn next_generation() -> u64 {
+  TRAPPED.store(false, atomic::Ordering::Relaxed);
+  heartbeat();
+  if !WATCHDOG.with(|w| w.replace(true)) {
+    ic_cdk_timers::set_timer_interval(WATCHDOG_INTERVAL, check);
+  }
+  GENERATION.fetch_add(1, atomic::Ordering::Relaxed) + 1
+}
+
+pub(super) fn is_current(generation: u64) -> bool {
+  GENERATION.load(atomic::Ordering::Relaxed) == generation
+}
+
+pub(super) fn heartbeat() {
+  HEARTBEAT.store(ic_cdk::api::time(), atomic::Ordering::Relaxed);
+}
+
+pub(super) fn reset_backoff() {
+  RESTARTS.store(0, atomic::Ordering::Relaxed);
+}
+
+/// Records an iteration that trapped at `now`, the loop is restarted once the stall timeout,
+/// backed off one more step, has elapsed.
+pub(super) fn trapped(now: u64) {
+  RESTARTS.fetch_add(1, atomic::Ordering::Relaxed);
+  HEARTBEAT.store(now, atomic::Ordering::Relaxed);
+  TRAPPED.store(true, atomic::Ordering::Relaxed);
+}
+
+fn check() {
+  let Some(elapsed) = stalled_for(ic_cdk::api::time()) else {
+    return;
+  };
+
+  let config = crate::index::mem_get_config();
+  let (height, _) = crate::index::next_block(config.network);
+  let restarts = if TRAPPED.load(atomic::Ordering::Relaxed) {
+    // the trap already counted as a restart
+    RESTARTS.load(atomic::Ordering::Relaxed)
+  } else {
+    RESTARTS.fetch_add(1, atomic::Ordering::Relaxed) + 1
+  };
+  log!(
+    CRITICAL,
+    "index loop stalled at height {} for {}s, restarting (attempt {})",
+    height,
+    elapsed / 1_000_000_000,
+    restarts
+  );
+  crate::metrics::observe_watchdog_restart();
+  let _ = update_index(config.network, config.subscribers);
+}
+
+/// Returns how long the loop has been without a heartbeat at `now` if it is due for a restart.
+fn stalled_for(now: u64) -> Option<u64> {
+  // a loop that was stopped or halted is not stalled, only a scheduled or trapped one can be
+  if is_shutting_down()
+    || crate::index::is_halted()
+    || !(crate::index::is_indexing()
+      || crate::index::is_in_flight()
+      || TRAPPED.load(atomic::Ordering::Relaxed))
+  {
+    return None;
+  }
+
+  let restarts = RESTARTS.load(atomic::Ordering::Relaxed);
+  let timeout = (STALL_TIMEOUT.as_nanos() as u64) << restarts.min(MAX_BACKOFF_SHIFT);
+  let elapsed = now.saturating_sub(HEARTBEAT.load(atomic::Ordering::Relaxed));
+  (elapsed >= timeout).then_some(elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn trapped_iteration_is_restarted_after_backoff() {
+    let timeout = STALL_TIMEOUT.as_nanos() as u64;
+    let now = 1_000;
+
+    // neither scheduled nor trapped, nothing to restart
+    assert_eq!(stalled_for(now + timeout), None);
+
+    // the trap counts as the first restart and doubles the timeout
+    trapped(now);
+    assert_eq!(stalled_for(now + 2 * timeout - 1), None);
+    assert_eq!(stalled_for(now + 2 * timeout), Some(2 * timeout));
+
+    crate::index::set_halted(true);
+    assert_eq!(stalled_for(now + 2 * timeout), None);
+    crate::index::set_halted(false);
+
+    // trapping again at the same height backs off further
+    trapped(now);
+    assert_eq!(stalled_for(now + 4 * timeout - 1), None);
+    assert_eq!(stalled_for(now + 4 * timeout), Some(4 * timeout));
+
+    reset_backoff();
+    assert_eq!(stalled_for(now + timeout), Some(timeout));
+  }
+}
@@ -3,6 +3,7 @@ pub mod config;
 pub mod index;
 mod into_usize;
 pub mod logs;
+pub mod metrics;
 mod notifier;
 pub mod rpc;
 
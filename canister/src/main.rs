@@ -1,15 +1,23 @@
-use bitcoin::{OutPoint, Txid};
+use bitcoin::{Address, OutPoint, Psbt, Transaction, Txid};
 use candid::{candid_method, Principal};
 use ic_canister_log::log;
 use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
-use ic_cdk_macros::{init, post_upgrade, query, update};
-use runes_indexer::config::RunesIndexerArgs;
-use runes_indexer::index::entry::Entry;
+use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
+use runes_indexer::config::{BasicAuth, RpcHeader, RpcProvider, RpcStrategy, RunesIndexerArgs};
+use runes_indexer::index::entry::{to_terms, Entry};
 use runes_indexer::logs::{CRITICAL, INFO, WARNING};
-use runes_indexer_interface::{Error, GetEtchingResult, RuneBalance, RuneEntry, Terms};
+use runes_indexer_interface::{
+  AddressRuneBalances, Artifact, BlockRunesSummary, Cenotaph, CertifiedOutPointBalances,
+  CertifiedRuneBalances, CertifiedRuneEntry, Edict, Error, Etching, GetEtchingResult,
+  GetRunesResult, GetRunestoneResult, IndexStats, IndexerStatus, MintError, MintStatus,
+  OutPointRuneAmounts, OutPointRuneBalances, OutPointSpender, OutputRuneAmounts, RuneAmount,
+  RuneBalance, RuneEntry, RuneHolder, RuneHolders, RuneMints, RuneSupply, RunesOrder, Runestone,
+  SimulateTransactionResult,
+};
 use std::str::FromStr;
 
 pub const MAX_OUTPOINTS: usize = 256;
+pub const MAX_RUNES: usize = 256;
 
 #[query]
 #[candid_method(query)]
@@ -18,6 +26,65 @@ pub fn get_latest_block() -> (u32, String) {
   (height, hash.to_string())
 }
 
+#[query]
+#[candid_method(query)]
+pub fn get_index_stats() -> IndexStats {
+  let latest_block = runes_indexer::index::mem_latest_block();
+
+  IndexStats {
+    latest_height: latest_block.map(|(height, _)| height),
+    latest_block_hash: latest_block.map(|(_, hash)| hash.to_string()),
+    runes: runes_indexer::index::mem_statistic_runes(),
+    reserved_runes: runes_indexer::index::mem_statistic_reserved_runes(),
+    rune_to_rune_id: runes_indexer::index::mem_length_rune_to_rune_id(),
+    rune_id_to_rune_entry: runes_indexer::index::mem_length_rune_id_to_rune_entry(),
+    transaction_id_to_rune: runes_indexer::index::mem_length_transaction_id_to_rune(),
+    outpoint_to_rune_balances: runes_indexer::index::mem_length_outpoint_to_rune_balances(),
+    outpoint_to_height: runes_indexer::index::mem_length_outpoint_to_height(),
+    script_hash_to_outpoints: runes_indexer::index::mem_length_script_hash_to_outpoints(),
+    script_hash_to_rune_totals: runes_indexer::index::mem_length_script_hash_to_rune_totals(),
+    outpoint_to_script_hash: runes_indexer::index::mem_length_outpoint_to_script_hash(),
+    rune_id_to_outpoints: runes_indexer::index::mem_length_rune_id_to_outpoints(),
+    number_to_rune_id: runes_indexer::index::mem_length_number_to_rune_id(),
+    rune_transactions: runes_indexer::index::mem_length_transaction_id_to_rune_transaction(),
+    spent_outpoints: runes_indexer::index::mem_length_outpoint_to_spent_outpoint(),
+    supply_changes: runes_indexer::index::mem_length_rune_id_height_to_supply(),
+    block_runes_summaries: runes_indexer::index::mem_length_block_runes_summary(),
+    certified_leaves: runes_indexer::index::certification::length_leaves(),
+    change_records: runes_indexer::index::mem_length_change_record(),
+    memory_sizes: runes_indexer::index::mem_memory_sizes(),
+  }
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_status() -> IndexerStatus {
+  let latest_block = runes_indexer::index::mem_latest_block();
+  let metrics = runes_indexer::metrics::get();
+  let height = latest_block.map(|(height, _)| height);
+
+  IndexerStatus {
+    height,
+    block_hash: latest_block.map(|(_, hash)| hash.to_string()),
+    bitcoin_tip_height: metrics.tip_height,
+    lag: height
+      .zip(metrics.tip_height)
+      .map(|(height, tip_height)| tip_height.saturating_sub(height)),
+    running: runes_indexer::index::is_indexing(),
+    shutting_down: runes_indexer::index::is_shutting_down(),
+    in_flight: runes_indexer::index::is_in_flight(),
+    halted: runes_indexer::index::is_halted(),
+    backfilling: !runes_indexer::index::backfill::is_done(),
+    address_index_start: runes_indexer::index::mem_get_address_index_start(),
+    last_error: metrics.last_error,
+    last_error_time: metrics.last_error_time,
+    last_block_time: metrics.last_block_time,
+    since_last_block: metrics
+      .last_block_time
+      .map(|time| ic_cdk::api::time().saturating_sub(time)),
+  }
+}
+
 #[query]
 #[candid_method(query)]
 pub fn get_etching(txid: String) -> Option<GetEtchingResult> {
@@ -33,62 +100,216 @@ pub fn get_etching(txid: String) -> Option<GetEtchingResult> {
 #[query]
 #[candid_method(query)]
 pub fn get_rune(str_spaced_rune: String) -> Option<RuneEntry> {
-  let spaced_rune = ordinals::SpacedRune::from_str(&str_spaced_rune).ok()?;
-  let rune_id_value = runes_indexer::index::mem_get_rune_to_rune_id(spaced_rune.rune.0)?;
-  let rune_entry = runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id_value)?;
   let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
-  Some(RuneEntry {
-    confirmations: cur_height - rune_entry.block as u32 + 1,
-    rune_id: ordinals::RuneId::load(rune_id_value).to_string(),
-    block: rune_entry.block,
-    burned: rune_entry.burned,
-    divisibility: rune_entry.divisibility,
-    etching: rune_entry.etching.to_string(),
-    mints: rune_entry.mints,
-    number: rune_entry.number,
-    premine: rune_entry.premine,
-    spaced_rune: rune_entry.spaced_rune.to_string(),
-    symbol: rune_entry.symbol.map(|c| c.to_string()),
-    terms: rune_entry.terms.map(|t| Terms {
-      amount: t.amount,
-      cap: t.cap,
-      height: t.height,
-      offset: t.offset,
-    }),
-    timestamp: rune_entry.timestamp,
-    turbo: rune_entry.turbo,
-  })
+  rune_by_name(&str_spaced_rune, cur_height)
 }
 
 #[query]
 #[candid_method(query)]
 pub fn get_rune_by_id(str_rune_id: String) -> Option<RuneEntry> {
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+  rune_by_id(&str_rune_id, cur_height)
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_runes_by_names(str_spaced_runes: Vec<String>) -> Result<Vec<Option<RuneEntry>>, Error> {
+  if str_spaced_runes.len() > MAX_RUNES {
+    return Err(Error::MaxRunesExceeded);
+  }
+
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+  Ok(
+    str_spaced_runes
+      .iter()
+      .map(|str_spaced_rune| rune_by_name(str_spaced_rune, cur_height))
+      .collect(),
+  )
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_runes_by_ids(str_rune_ids: Vec<String>) -> Result<Vec<Option<RuneEntry>>, Error> {
+  if str_rune_ids.len() > MAX_RUNES {
+    return Err(Error::MaxRunesExceeded);
+  }
+
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+  Ok(
+    str_rune_ids
+      .iter()
+      .map(|str_rune_id| rune_by_id(str_rune_id, cur_height))
+      .collect(),
+  )
+}
+
+fn rune_by_name(str_spaced_rune: &str, cur_height: u32) -> Option<RuneEntry> {
+  let spaced_rune = ordinals::SpacedRune::from_str(str_spaced_rune).ok()?;
+  let rune_id_value = runes_indexer::index::mem_get_rune_to_rune_id(spaced_rune.rune.0)?;
+  let rune_entry = runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id_value)?;
+  Some(to_rune_entry(
+    ordinals::RuneId::load(rune_id_value),
+    rune_entry,
+    cur_height,
+  ))
+}
+
+fn rune_by_id(str_rune_id: &str, cur_height: u32) -> Option<RuneEntry> {
+  let rune_id = ordinals::RuneId::from_str(str_rune_id).ok()?;
+  let rune_entry = runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id.store())?;
+  Some(to_rune_entry(rune_id, rune_entry, cur_height))
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_mint_status(str_rune_id: String) -> Option<MintStatus> {
   let rune_id = ordinals::RuneId::from_str(&str_rune_id).ok()?;
   let rune_entry = runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id.store())?;
-  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
-  Some(RuneEntry {
-    confirmations: cur_height - rune_entry.block as u32 + 1,
-    rune_id: str_rune_id,
-    block: rune_entry.block,
-    burned: rune_entry.burned,
-    divisibility: rune_entry.divisibility,
-    etching: rune_entry.etching.to_string(),
+  let height =
+    u64::from(runes_indexer::index::mem_latest_block_height().expect("No block height found")) + 1;
+
+  let (mintable, error) = match rune_entry.mintable(height) {
+    Ok(_) => (true, None),
+    Err(e) => (
+      false,
+      Some(match e {
+        runes_indexer::index::entry::MintError::Cap(cap) => MintError::Cap(cap),
+        runes_indexer::index::entry::MintError::End(end) => MintError::End(end),
+        runes_indexer::index::entry::MintError::Start(start) => MintError::Start(start),
+        runes_indexer::index::entry::MintError::Unmintable => MintError::Unmintable,
+      }),
+    ),
+  };
+  let cap = rune_entry.terms.and_then(|terms| terms.cap);
+
+  Some(MintStatus {
+    rune_id: rune_id.to_string(),
+    height,
+    mintable,
+    error,
+    amount: rune_entry.terms.and_then(|terms| terms.amount),
     mints: rune_entry.mints,
-    number: rune_entry.number,
-    premine: rune_entry.premine,
-    spaced_rune: rune_entry.spaced_rune.to_string(),
-    symbol: rune_entry.symbol.map(|c| c.to_string()),
-    terms: rune_entry.terms.map(|t| Terms {
-      amount: t.amount,
-      cap: t.cap,
-      height: t.height,
-      offset: t.offset,
-    }),
-    timestamp: rune_entry.timestamp,
-    turbo: rune_entry.turbo,
+    cap,
+    remaining: cap.unwrap_or_default().saturating_sub(rune_entry.mints),
+    start: rune_entry.start(),
+    end: rune_entry.end(),
+    supply: rune_entry.supply(),
+    max_supply: rune_entry.max_supply(),
   })
 }
 
+#[query]
+#[candid_method(query)]
+pub fn get_runes(
+  cursor: Option<String>,
+  limit: u32,
+  order: RunesOrder,
+) -> Result<GetRunesResult, Error> {
+  if limit as usize > MAX_RUNES {
+    return Err(Error::MaxRunesExceeded);
+  }
+
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+  let limit = limit as usize;
+
+  let (runes, next_cursor) = match order {
+    RunesOrder::Number => {
+      let cursor = cursor
+        .map(|cursor| u64::from_str(&cursor))
+        .transpose()
+        .map_err(|_| Error::InvalidCursor)?;
+      let mut ids = runes_indexer::index::mem_get_number_to_rune_ids(cursor, limit + 1);
+      let next_cursor = if ids.len() > limit {
+        ids.truncate(limit);
+        ids.last().map(|(number, _)| number.to_string())
+      } else {
+        None
+      };
+      let runes = ids
+        .into_iter()
+        .filter_map(|(_, rune_id_value)| {
+          runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id_value).map(|rune_entry| {
+            to_rune_entry(
+              ordinals::RuneId::load(rune_id_value),
+              rune_entry,
+              cur_height,
+            )
+          })
+        })
+        .collect();
+      (runes, next_cursor)
+    }
+    RunesOrder::Block => {
+      let cursor = cursor
+        .map(|cursor| ordinals::RuneId::from_str(&cursor).map(|rune_id| rune_id.store()))
+        .transpose()
+        .map_err(|_| Error::InvalidCursor)?;
+      let mut entries = runes_indexer::index::mem_get_rune_id_to_rune_entries(cursor, limit + 1);
+      let next_cursor = if entries.len() > limit {
+        entries.truncate(limit);
+        entries
+          .last()
+          .map(|(rune_id_value, _)| ordinals::RuneId::load(*rune_id_value).to_string())
+      } else {
+        None
+      };
+      let runes = entries
+        .into_iter()
+        .map(|(rune_id_value, rune_entry)| {
+          to_rune_entry(
+            ordinals::RuneId::load(rune_id_value),
+            rune_entry,
+            cur_height,
+          )
+        })
+        .collect();
+      (runes, next_cursor)
+    }
+  };
+
+  Ok(GetRunesResult { runes, next_cursor })
+}
+
+#[query]
+#[candid_method(query)]
+pub fn search_runes(query: String, limit: u32) -> Result<Vec<RuneEntry>, Error> {
+  if limit as usize > MAX_RUNES {
+    return Err(Error::MaxRunesExceeded);
+  }
+
+  let Some(prefix) = runes_indexer::index::normalize_rune_name(&query) else {
+    return Ok(vec![]);
+  };
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+
+  Ok(
+    runes_indexer::index::mem_search_runes(&prefix, limit as usize)
+      .into_iter()
+      .filter_map(|(_, rune_id_value)| {
+        runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id_value).map(|rune_entry| {
+          to_rune_entry(
+            ordinals::RuneId::load(rune_id_value),
+            rune_entry,
+            cur_height,
+          )
+        })
+      })
+      .collect(),
+  )
+}
+
+fn to_rune_entry(
+  rune_id: ordinals::RuneId,
+  rune_entry: runes_indexer::index::entry::RuneEntry,
+  cur_height: u32,
+) -> RuneEntry {
+  rune_entry.to_interface(
+    rune_id,
+    cur_height - rune_entry.block as u32 + 1,
+    runes_indexer::index::mem_get_rune_id_to_outpoint_count(rune_id.store()),
+  )
+}
+
 #[query]
 #[candid_method(query)]
 pub fn get_rune_balances_for_outputs(
@@ -110,47 +331,499 @@ pub fn get_rune_balances_for_outputs(
         continue;
       }
     };
-    let k = OutPoint::store(outpoint);
-    if let Some(rune_balances) = runes_indexer::index::mem_get_outpoint_to_rune_balances(k) {
-      if let Some(height) = runes_indexer::index::mem_get_outpoint_to_height(k) {
-        let confirmations = cur_height - height + 1;
-
-        let mut outpoint_balances = Vec::new();
-        for rune_balance in rune_balances.balances.iter() {
-          let rune_entry =
-            runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_balance.rune_id.store());
-          if let Some(rune_entry) = rune_entry {
-            outpoint_balances.push(RuneBalance {
-              confirmations,
-              rune_id: rune_balance.rune_id.to_string(),
-              amount: rune_balance.balance,
-              divisibility: rune_entry.divisibility,
-              symbol: rune_entry.symbol.map(|c| c.to_string()),
-            });
-          } else {
-            log!(
-              CRITICAL,
-              "Rune not found for rune_id {}",
-              rune_balance.rune_id.to_string()
-            );
-          }
+    piles.push(rune_balances_for_outpoint(outpoint, cur_height));
+  }
+
+  Ok(piles)
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_certified_rune_balances_for_outputs(
+  outpoints: Vec<String>,
+) -> Result<CertifiedRuneBalances, Error> {
+  if outpoints.len() > MAX_OUTPOINTS {
+    return Err(Error::MaxOutpointsExceeded);
+  }
+  if !runes_indexer::index::backfill::is_done() {
+    return Err(Error::CertificateUnavailable);
+  }
+  let certificate = ic_cdk::api::data_certificate().ok_or(Error::CertificateUnavailable)?;
+  let (height, block_hash) =
+    runes_indexer::index::mem_latest_block().ok_or(Error::CertificateUnavailable)?;
+
+  let mut outputs = Vec::new();
+  for str_outpoint in outpoints {
+    let outpoint = OutPoint::from_str(&str_outpoint).map_err(|_| Error::InvalidOutPoint)?;
+    outputs.push(CertifiedOutPointBalances {
+      outpoint: outpoint.to_string(),
+      balances: certified_rune_balances_for_outpoint(outpoint, height)?,
+      witness: runes_indexer::index::certification::outpoint_witness(outpoint),
+    });
+  }
+
+  Ok(CertifiedRuneBalances {
+    certificate,
+    height,
+    block_hash: block_hash.to_string(),
+    outputs,
+  })
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_certified_rune_by_id(str_rune_id: String) -> Result<CertifiedRuneEntry, Error> {
+  let rune_id = ordinals::RuneId::from_str(&str_rune_id).map_err(|_| Error::InvalidRuneId)?;
+  if !runes_indexer::index::backfill::is_done() {
+    return Err(Error::CertificateUnavailable);
+  }
+  let certificate = ic_cdk::api::data_certificate().ok_or(Error::CertificateUnavailable)?;
+  let (height, block_hash) =
+    runes_indexer::index::mem_latest_block().ok_or(Error::CertificateUnavailable)?;
+
+  Ok(CertifiedRuneEntry {
+    certificate,
+    height,
+    block_hash: block_hash.to_string(),
+    rune_id: rune_id.to_string(),
+    entry: runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id.store())
+      .map(|rune_entry| to_rune_entry(rune_id, rune_entry, height)),
+    witness: runes_indexer::index::certification::rune_witness(rune_id),
+  })
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_rune_balances_for_outputs_at(
+  outpoints: Vec<String>,
+  height: u32,
+) -> Result<Vec<Option<Vec<RuneBalance>>>, Error> {
+  if outpoints.len() > MAX_OUTPOINTS {
+    return Err(Error::MaxOutpointsExceeded);
+  }
+  check_history_height(height)?;
+
+  Ok(
+    outpoints
+      .iter()
+      .map(|str_outpoint| {
+        let outpoint = OutPoint::from_str(str_outpoint).ok()?;
+        rune_balances_for_outpoint_at(outpoint, height)
+      })
+      .collect(),
+  )
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_block_runes_summary(height: u32) -> Option<BlockRunesSummary> {
+  let summary = runes_indexer::index::mem_get_block_runes_summary(height)?;
+
+  Some(BlockRunesSummary {
+    height,
+    block_hash: summary.block_hash.to_string(),
+    timestamp: summary.timestamp.into(),
+    etched: summary
+      .etched
+      .iter()
+      .map(|rune_id| rune_id.to_string())
+      .collect(),
+    mints: summary
+      .mints
+      .into_iter()
+      .map(|(rune_id, mints)| {
+        let amount = runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id.store())
+          .and_then(|entry| entry.terms)
+          .and_then(|terms| terms.amount)
+          .unwrap_or_default();
+        RuneMints {
+          rune_id: rune_id.to_string(),
+          mints,
+          amount: amount.saturating_mul(mints),
         }
-        piles.push(Some(outpoint_balances));
-      } else {
-        log!(WARNING, "Height not found for outpoint {}", str_outpoint);
-        piles.push(None);
-      }
+      })
+      .collect(),
+    burned: to_rune_amounts(summary.burned),
+    transactions: summary.transactions,
+  })
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_outpoint_spender(outpoint: String) -> Option<OutPointSpender> {
+  let outpoint = OutPoint::from_str(&outpoint).ok()?;
+  let spent = runes_indexer::index::mem_get_outpoint_to_spent_outpoint(outpoint.store())?;
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+
+  Some(OutPointSpender {
+    outpoint: outpoint.to_string(),
+    height: spent.height,
+    spender: spent.spender.to_string(),
+    spent_height: spent.spent_height,
+    confirmations: cur_height - spent.spent_height + 1,
+    rune_balances: to_rune_amounts(spent.rune_balances.balances),
+  })
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_rune_supply_at(str_rune_id: String, height: u32) -> Result<Option<RuneSupply>, Error> {
+  let rune_id = ordinals::RuneId::from_str(&str_rune_id).map_err(|_| Error::InvalidRuneId)?;
+  check_history_height(height)?;
+
+  let Some(mut rune_entry) = runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_id.store())
+  else {
+    return Ok(None);
+  };
+  if rune_entry.block > u64::from(height) {
+    return Ok(None);
+  }
+  if let Some((mints, burned)) =
+    runes_indexer::index::mem_get_rune_id_supply_after(rune_id.store(), height)
+  {
+    rune_entry.mints = mints;
+    rune_entry.burned = burned;
+  }
+
+  Ok(Some(RuneSupply {
+    rune_id: rune_id.to_string(),
+    height,
+    premine: rune_entry.premine,
+    mints: rune_entry.mints,
+    burned: rune_entry.burned,
+    supply: rune_entry.supply(),
+  }))
+}
+
+/// Historical queries are answered from the height history mode was enabled at up to the tip.
+fn check_history_height(height: u32) -> Result<(), Error> {
+  let Some(history_start) = runes_indexer::index::mem_get_config().history_start else {
+    return Err(Error::HistoryDisabled);
+  };
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+  if height < history_start || height > cur_height {
+    return Err(Error::HeightNotAvailable);
+  }
+  Ok(())
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_rune_balances_for_address(
+  address: String,
+  cursor: Option<String>,
+  limit: u32,
+) -> Result<AddressRuneBalances, Error> {
+  if limit as usize > MAX_OUTPOINTS {
+    return Err(Error::MaxOutpointsExceeded);
+  }
+
+  let network = runes_indexer::index::mem_get_config().network;
+  let address = Address::from_str(&address)
+    .ok()
+    .and_then(|address| {
+      address
+        .require_network(runes_indexer::index::bitcoin_network(network))
+        .ok()
+    })
+    .ok_or(Error::InvalidAddress)?;
+  let cursor = cursor
+    .map(|cursor| OutPoint::from_str(&cursor).map(OutPoint::store))
+    .transpose()
+    .map_err(|_| Error::InvalidCursor)?;
+  if runes_indexer::index::mem_get_address_index_start() > 0 {
+    return Err(Error::AddressIndexIncomplete);
+  }
+
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+  let script_hash = runes_indexer::index::script_hash(&address.script_pubkey());
+
+  let mut page =
+    runes_indexer::index::mem_get_script_hash_to_outpoints(script_hash, cursor, limit as usize + 1)
+      .into_iter()
+      .map(OutPoint::load)
+      .collect::<Vec<_>>();
+  let next_cursor = if page.len() > limit as usize {
+    page.truncate(limit as usize);
+    page.last().map(|outpoint| outpoint.to_string())
+  } else {
+    None
+  };
+
+  Ok(AddressRuneBalances {
+    outpoints: page
+      .into_iter()
+      .filter_map(|outpoint| {
+        rune_balances_for_outpoint(outpoint, cur_height).map(|balances| OutPointRuneBalances {
+          outpoint: outpoint.to_string(),
+          balances,
+        })
+      })
+      .collect(),
+    totals: runes_indexer::index::mem_get_script_hash_to_rune_totals(script_hash)
+      .into_iter()
+      .map(|(rune_id_value, amount)| RuneAmount {
+        rune_id: ordinals::RuneId::load(rune_id_value).to_string(),
+        amount,
+      })
+      .collect(),
+    next_cursor,
+  })
+}
+
+#[query]
+#[candid_method(query)]
+pub fn get_rune_holders(
+  str_rune_id: String,
+  cursor: Option<String>,
+  limit: u32,
+) -> Result<RuneHolders, Error> {
+  if limit as usize > MAX_OUTPOINTS {
+    return Err(Error::MaxOutpointsExceeded);
+  }
+
+  let rune_id = ordinals::RuneId::from_str(&str_rune_id).map_err(|_| Error::InvalidRuneId)?;
+  let cursor = cursor
+    .map(|cursor| OutPoint::from_str(&cursor).map(OutPoint::store))
+    .transpose()
+    .map_err(|_| Error::InvalidCursor)?;
+  if !runes_indexer::index::backfill::is_done() {
+    return Err(Error::Backfilling);
+  }
+
+  let mut holders =
+    runes_indexer::index::mem_get_rune_id_to_outpoints(rune_id.store(), cursor, limit as usize + 1);
+  let next_cursor = if holders.len() > limit as usize {
+    holders.truncate(limit as usize);
+    holders
+      .last()
+      .map(|(outpoint, _)| OutPoint::load(*outpoint).to_string())
+  } else {
+    None
+  };
+
+  Ok(RuneHolders {
+    holders: holders
+      .into_iter()
+      .map(|(outpoint, amount)| RuneHolder {
+        outpoint: OutPoint::load(outpoint).to_string(),
+        amount,
+        height: runes_indexer::index::mem_get_outpoint_to_height(outpoint).unwrap_or_default(),
+      })
+      .collect(),
+    next_cursor,
+  })
+}
+
+fn rune_balances_for_outpoint(outpoint: OutPoint, cur_height: u32) -> Option<Vec<RuneBalance>> {
+  let (rune_balances, height) = stored_rune_balances(outpoint)?;
+
+  Some(to_rune_balances(&rune_balances, cur_height - height + 1))
+}
+
+/// Fails rather than leaving out a balance, the certified hash covers all balances of an outpoint.
+fn certified_rune_balances_for_outpoint(
+  outpoint: OutPoint,
+  cur_height: u32,
+) -> Result<Option<Vec<RuneBalance>>, Error> {
+  let Some((rune_balances, height)) = stored_rune_balances(outpoint) else {
+    return Ok(None);
+  };
+  let balances = to_rune_balances(&rune_balances, cur_height - height + 1);
+  if balances.len() != rune_balances.balances.len() {
+    return Err(Error::RuneNotFound);
+  }
+
+  Ok(Some(balances))
+}
+
+fn stored_rune_balances(
+  outpoint: OutPoint,
+) -> Option<(runes_indexer::index::entry::RuneBalances, u32)> {
+  let k = OutPoint::store(outpoint);
+  let Some(rune_balances) = runes_indexer::index::mem_get_outpoint_to_rune_balances(k) else {
+    log!(WARNING, "Rune balances not found for outpoint {}", outpoint);
+    return None;
+  };
+  let Some(height) = runes_indexer::index::mem_get_outpoint_to_height(k) else {
+    log!(WARNING, "Height not found for outpoint {}", outpoint);
+    return None;
+  };
+
+  Some((rune_balances, height))
+}
+
+/// Balances of an outpoint at the end of block `at`, if it existed and was unspent then.
+fn rune_balances_for_outpoint_at(outpoint: OutPoint, at: u32) -> Option<Vec<RuneBalance>> {
+  let k = OutPoint::store(outpoint);
+  let (rune_balances, height) = match runes_indexer::index::mem_get_outpoint_to_spent_outpoint(k) {
+    Some(spent) if at < spent.spent_height => (spent.rune_balances, spent.height),
+    Some(_) => return None,
+    None => (
+      runes_indexer::index::mem_get_outpoint_to_rune_balances(k)?,
+      runes_indexer::index::mem_get_outpoint_to_height(k)?,
+    ),
+  };
+  if height > at {
+    return None;
+  }
+
+  Some(to_rune_balances(&rune_balances, at - height + 1))
+}
+
+fn to_rune_balances(
+  rune_balances: &runes_indexer::index::entry::RuneBalances,
+  confirmations: u32,
+) -> Vec<RuneBalance> {
+  let mut outpoint_balances = Vec::new();
+  for rune_balance in rune_balances.balances.iter() {
+    let rune_entry =
+      runes_indexer::index::mem_get_rune_id_to_rune_entry(rune_balance.rune_id.store());
+    if let Some(rune_entry) = rune_entry {
+      outpoint_balances.push(RuneBalance {
+        confirmations,
+        rune_id: rune_balance.rune_id.to_string(),
+        amount: rune_balance.balance,
+        divisibility: rune_entry.divisibility,
+        symbol: rune_entry.symbol.map(|c| c.to_string()),
+      });
     } else {
       log!(
-        WARNING,
-        "Rune balances not found for outpoint {}",
-        str_outpoint
+        CRITICAL,
+        "Rune not found for rune_id {}",
+        rune_balance.rune_id.to_string()
       );
-      piles.push(None);
     }
   }
+  outpoint_balances
+}
 
-  Ok(piles)
+#[query]
+#[candid_method(query)]
+pub fn get_runestone(txid: String) -> Option<GetRunestoneResult> {
+  let txid = Txid::from_str(&txid).ok()?;
+  let rune_transaction = runes_indexer::index::mem_get_rune_transaction(txid)?;
+  let cur_height = runes_indexer::index::mem_latest_block_height().expect("No block height found");
+
+  Some(GetRunestoneResult {
+    confirmations: cur_height - rune_transaction.height + 1,
+    height: rune_transaction.height,
+    tx_index: rune_transaction.tx_index,
+    artifact: rune_transaction.artifact.map(to_artifact),
+    inputs: to_outpoint_rune_amounts(rune_transaction.inputs),
+    minted: rune_transaction.minted.map(to_rune_amount),
+    etched: rune_transaction
+      .etched
+      .map(|(rune_id, _)| rune_id.to_string()),
+    pointer: rune_transaction.pointer,
+    allocations: to_output_rune_amounts(rune_transaction.allocations),
+    burned: to_rune_amounts(rune_transaction.burned),
+  })
+}
+
+/// Runs a hex encoded transaction or PSBT through the rune allocation logic against the current
+/// state, as if it were included in the next block, without indexing it.
+#[query]
+#[candid_method(query)]
+pub fn simulate_transaction(tx_hex: String) -> Result<SimulateTransactionResult, Error> {
+  let bytes = hex::decode(tx_hex.trim()).map_err(|_| Error::InvalidTransaction)?;
+  let tx = bitcoin::consensus::deserialize::<Transaction>(&bytes)
+    .or_else(|_| Psbt::deserialize(&bytes).map(|psbt| psbt.unsigned_tx))
+    .map_err(|_| Error::InvalidTransaction)?;
+
+  let network = runes_indexer::index::mem_get_config().network;
+  let simulation = runes_indexer::index::updater::simulate_transaction(network, &tx)
+    .map_err(|_| Error::InvalidTransaction)?;
+  let transaction = simulation.transaction;
+
+  Ok(SimulateTransactionResult {
+    artifact: transaction.artifact.map(to_artifact),
+    inputs: to_outpoint_rune_amounts(transaction.inputs),
+    minted: transaction.minted.map(to_rune_amount),
+    mint_error: simulation.mint_error,
+    etched: transaction.etched.map(|(_, rune)| rune.to_string()),
+    etching_error: simulation.etching_error,
+    pointer: transaction.pointer,
+    allocations: to_output_rune_amounts(transaction.allocations),
+    burned: to_rune_amounts(transaction.burned),
+  })
+}
+
+fn to_rune_amount((rune_id, amount): (ordinals::RuneId, u128)) -> RuneAmount {
+  RuneAmount {
+    rune_id: rune_id.to_string(),
+    amount,
+  }
+}
+
+fn to_rune_amounts(
+  rune_balances: Vec<runes_indexer::index::entry::RuneBalance>,
+) -> Vec<RuneAmount> {
+  rune_balances
+    .into_iter()
+    .map(|rune_balance| to_rune_amount((rune_balance.rune_id, rune_balance.balance)))
+    .collect()
+}
+
+fn to_outpoint_rune_amounts(
+  inputs: Vec<(OutPoint, runes_indexer::index::entry::RuneBalances)>,
+) -> Vec<OutPointRuneAmounts> {
+  inputs
+    .into_iter()
+    .map(|(outpoint, rune_balances)| OutPointRuneAmounts {
+      outpoint: outpoint.to_string(),
+      amounts: to_rune_amounts(rune_balances.balances),
+    })
+    .collect()
+}
+
+fn to_output_rune_amounts(
+  allocations: Vec<(u32, runes_indexer::index::entry::RuneBalances)>,
+) -> Vec<OutputRuneAmounts> {
+  allocations
+    .into_iter()
+    .map(|(vout, rune_balances)| OutputRuneAmounts {
+      vout,
+      amounts: to_rune_amounts(rune_balances.balances),
+    })
+    .collect()
+}
+
+fn to_artifact(artifact: ordinals::Artifact) -> Artifact {
+  match artifact {
+    ordinals::Artifact::Runestone(runestone) => Artifact::Runestone(Runestone {
+      edicts: runestone
+        .edicts
+        .into_iter()
+        .map(|edict| Edict {
+          rune_id: edict.id.to_string(),
+          amount: edict.amount,
+          output: edict.output,
+        })
+        .collect(),
+      etching: runestone.etching.map(|etching| Etching {
+        spaced_rune: etching.rune.map(|rune| {
+          ordinals::SpacedRune {
+            rune,
+            spacers: etching.spacers.unwrap_or_default(),
+          }
+          .to_string()
+        }),
+        divisibility: etching.divisibility,
+        premine: etching.premine,
+        symbol: etching.symbol.map(|c| c.to_string()),
+        terms: etching.terms.map(to_terms),
+        turbo: etching.turbo,
+      }),
+      mint: runestone.mint.map(|rune_id| rune_id.to_string()),
+      pointer: runestone.pointer,
+    }),
+    ordinals::Artifact::Cenotaph(cenotaph) => Artifact::Cenotaph(Cenotaph {
+      etching: cenotaph.etching.map(|rune| rune.to_string()),
+      flaw: cenotaph.flaw.map(|flaw| flaw.to_string()),
+      mint: cenotaph.mint.map(|rune_id| rune_id.to_string()),
+    }),
+  }
 }
 
 #[query(hidden = true)]
@@ -175,13 +848,13 @@ pub fn start() -> Result<(), String> {
     return Err("Not authorized".to_string());
   }
 
-  runes_indexer::index::cancel_shutdown();
-  let config = runes_indexer::index::mem_get_config();
-  let _ = runes_indexer::index::updater::update_index(config.network, config.subscribers);
+  set_running(true);
 
   Ok(())
 }
 
+/// Stops the index loop, the block in flight, if any, is still indexed and `get_status` reports
+/// `in_flight` until it is.
 #[update(hidden = true)]
 pub fn stop() -> Result<(), String> {
   let caller = ic_cdk::api::caller();
@@ -189,12 +862,26 @@ pub fn stop() -> Result<(), String> {
     return Err("Not authorized".to_string());
   }
 
-  runes_indexer::index::shut_down();
-  log!(INFO, "Waiting for index thread to finish...");
+  set_running(false);
+  log!(INFO, "Stopping index thread...");
 
   Ok(())
 }
 
+/// Persists the run state and starts or stops the index loop accordingly.
+fn set_running(running: bool) {
+  let mut config = runes_indexer::index::mem_get_config();
+  config.running = Some(running);
+  runes_indexer::index::mem_set_config(config.clone()).unwrap();
+
+  if running {
+    runes_indexer::index::cancel_shutdown();
+    let _ = runes_indexer::index::updater::update_index(config.network, config.subscribers);
+  } else {
+    runes_indexer::index::shut_down();
+  }
+}
+
 #[update(hidden = true)]
 pub fn set_bitcoin_rpc_url(url: String) -> Result<(), String> {
   let caller = ic_cdk::api::caller();
@@ -208,6 +895,63 @@ pub fn set_bitcoin_rpc_url(url: String) -> Result<(), String> {
   Ok(())
 }
 
+/// Replaces the RPC providers, and the strategy if given.
+#[update(hidden = true)]
+pub fn set_rpc_providers(
+  providers: Vec<RpcProvider>,
+  strategy: Option<RpcStrategy>,
+) -> Result<(), String> {
+  let caller = ic_cdk::api::caller();
+  if !ic_cdk::api::is_controller(&caller) {
+    return Err("Not authorized".to_string());
+  }
+  let mut config = runes_indexer::index::mem_get_config();
+  config.rpc_providers = Some(providers);
+  if strategy.is_some() {
+    config.rpc_strategy = strategy;
+  }
+  config.validate_rpc_strategy()?;
+  runes_indexer::index::mem_set_config(config).unwrap();
+
+  Ok(())
+}
+
+/// Rotates the headers and basic auth credentials of the provider at `url`.
+#[update(hidden = true)]
+pub fn set_rpc_provider_credentials(
+  url: String,
+  headers: Option<Vec<RpcHeader>>,
+  basic_auth: Option<BasicAuth>,
+) -> Result<(), String> {
+  let caller = ic_cdk::api::caller();
+  if !ic_cdk::api::is_controller(&caller) {
+    return Err("Not authorized".to_string());
+  }
+  let mut config = runes_indexer::index::mem_get_config();
+  let mut providers = config.get_rpc_providers();
+  let provider = providers
+    .iter_mut()
+    .find(|provider| provider.url == url)
+    .ok_or_else(|| "Unknown provider".to_string())?;
+  provider.headers = headers;
+  provider.basic_auth = basic_auth;
+  config.rpc_providers = Some(providers);
+  runes_indexer::index::mem_set_config(config).unwrap();
+  log!(INFO, "rpc provider credentials rotated");
+
+  Ok(())
+}
+
+/// The RPC providers with their secrets redacted.
+#[query(hidden = true)]
+pub fn get_rpc_providers() -> Vec<RpcProvider> {
+  runes_indexer::index::mem_get_config()
+    .get_rpc_providers()
+    .iter()
+    .map(RpcProvider::redacted)
+    .collect()
+}
+
 #[query(hidden = true)]
 pub fn get_subscribers() -> Vec<Principal> {
   runes_indexer::index::mem_get_config().subscribers
@@ -222,6 +966,11 @@ fn http_request(
   }
   if req.path() == "/logs" {
     runes_indexer::logs::do_reply(req)
+  } else if req.path() == "/metrics" {
+    ic_canisters_http_types::HttpResponseBuilder::ok()
+      .header("Content-Type", "text/plain; version=0.0.4")
+      .with_body_and_content_length(runes_indexer::metrics::encode())
+      .build()
   } else {
     ic_canisters_http_types::HttpResponseBuilder::not_found().build()
   }
@@ -232,7 +981,12 @@ fn http_request(
 fn init(runes_indexer_args: RunesIndexerArgs) {
   match runes_indexer_args {
     RunesIndexerArgs::Init(config) => {
+      if let Err(e) = config.validate_rpc_strategy() {
+        ic_cdk::trap(&e);
+      }
       runes_indexer::index::mem_set_config(config).unwrap();
+      runes_indexer::index::backfill::skip();
+      runes_indexer::index::start_address_index();
     }
     RunesIndexerArgs::Upgrade(_) => ic_cdk::trap(
       "Cannot initialize the canister with an Upgrade argument. Please provide an Init argument.",
@@ -240,8 +994,23 @@ fn init(runes_indexer_args: RunesIndexerArgs) {
   }
 }
 
+/// Refuses an upgrade in the middle of an iteration, whose awaited calls would never return.
+#[pre_upgrade]
+fn pre_upgrade() {
+  if runes_indexer::index::is_in_flight() {
+    ic_cdk::trap(
+      "upgrade refused: a block is being indexed, call stop and retry once get_status reports in_flight = false",
+    );
+  }
+}
+
 #[post_upgrade]
 fn post_upgrade(runes_indexer_args: Option<RunesIndexerArgs>) {
+  runes_indexer::index::backfill_number_to_rune_id();
+  runes_indexer::index::backfill::resume();
+  runes_indexer::index::certification::certify();
+  runes_indexer::index::start_address_index();
+
   match runes_indexer_args {
     Some(RunesIndexerArgs::Upgrade(Some(upgrade_args))) => {
       let mut config = runes_indexer::index::mem_get_config();
@@ -252,6 +1021,46 @@ fn post_upgrade(runes_indexer_args: Option<RunesIndexerArgs>) {
         config.subscribers = subscribers;
         log!(INFO, "subscribers updated: {:?}", config.subscribers);
       }
+      if let Some(retention) = upgrade_args.rune_transaction_retention {
+        config.rune_transaction_retention = Some(retention);
+      }
+      if let Some(retention) = upgrade_args.spent_outpoint_retention {
+        config.spent_outpoint_retention = Some(retention);
+      }
+      match upgrade_args.history {
+        Some(true) if config.history_start.is_none() => {
+          config.history_start = Some(
+            runes_indexer::index::mem_latest_block_height()
+              .map(|height| height + 1)
+              .unwrap_or(0),
+          );
+          log!(
+            INFO,
+            "history enabled from height {:?}",
+            config.history_start
+          );
+        }
+        Some(false) => {
+          config.history_start = None;
+          log!(INFO, "history disabled");
+        }
+        _ => {}
+      }
+      if let Some(providers) = upgrade_args.rpc_providers {
+        config.rpc_providers = Some(providers);
+      }
+      if let Some(strategy) = upgrade_args.rpc_strategy {
+        config.rpc_strategy = Some(strategy);
+      }
+      if let Some(budget) = upgrade_args.instruction_budget {
+        config.instruction_budget = Some(budget);
+      }
+      if let Some(running) = upgrade_args.running {
+        config.running = Some(running);
+      }
+      if let Err(e) = config.validate_rpc_strategy() {
+        ic_cdk::trap(&e);
+      }
       runes_indexer::index::mem_set_config(config).unwrap();
     }
     None | Some(RunesIndexerArgs::Upgrade(None)) => {}
@@ -259,6 +1068,11 @@ fn post_upgrade(runes_indexer_args: Option<RunesIndexerArgs>) {
       "Cannot upgrade the canister with an Init argument. Please provide an Upgrade argument.",
     ),
   }
+
+  if runes_indexer::index::mem_get_config().running == Some(true) {
+    log!(INFO, "resuming index thread after upgrade");
+    set_running(true);
+  }
 }
 
 ic_cdk::export_candid!();
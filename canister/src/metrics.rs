@@ -0,0 +1,284 @@
+use crate::index::entry::Versioned;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Counters exported on `/metrics`, kept on the heap and saved to stable memory with the index.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Metrics {
+  pub blocks_indexed: u64,
+  /// Number of reorgs handled, by depth.
+  pub reorgs: BTreeMap<u32, u64>,
+  pub rpc_calls: BTreeMap<String, u64>,
+  pub rpc_failures: BTreeMap<String, u64>,
+  /// Cycles spent on HTTPS outcalls and Bitcoin API calls, refunds deducted.
+  pub outcall_cycles: u128,
+  pub notification_failures: u64,
+  /// Tip height last reported by the Bitcoin API.
+  pub tip_height: Option<u32>,
+  /// Time in nanoseconds of the last successfully indexed block.
+  pub last_block_time: Option<u64>,
+  pub last_error: Option<String>,
+  pub last_error_time: Option<u64>,
+  /// Stalled index loops restarted by the watchdog.
+  pub watchdog_restarts: u64,
+  /// Calls by RPC provider, keyed by its position in the configuration, scheme and host.
+  pub provider_calls: BTreeMap<String, u64>,
+  pub provider_failures: BTreeMap<String, u64>,
+  /// Results that differed from the one most providers agreed on under `RpcStrategy::Quorum`.
+  pub provider_disagreements: BTreeMap<String, u64>,
+}
+
+impl Versioned for Metrics {
+  const VERSION: u8 = 1;
+}
+
+impl Storable for Metrics {
+  fn to_bytes(&self) -> Cow<[u8]> {
+    Cow::Owned(self.to_versioned_bytes())
+  }
+
+  fn from_bytes(bytes: Cow<[u8]>) -> Self {
+    Self::from_versioned_bytes(&bytes)
+  }
+
+  const BOUND: Bound = Bound::Unbounded;
+}
+
+thread_local! {
+  // loaded from stable memory on first use, written back with every committed block or reorg
+  static METRICS: RefCell<Metrics> = RefCell::new(crate::index::mem_get_metrics());
+}
+
+pub fn get() -> Metrics {
+  METRICS.with(|m| m.borrow().clone())
+}
+
+/// Tip height last reported by the Bitcoin API.
+pub fn tip_height() -> Option<u32> {
+  METRICS.with(|m| m.borrow().tip_height)
+}
+
+/// Writes the metrics to stable memory, in the same message as the index changes they count.
+fn save() {
+  crate::index::mem_set_metrics(get());
+}
+
+fn update(f: impl FnOnce(&mut Metrics)) {
+  METRICS.with(|m| f(&mut m.borrow_mut()));
+}
+
+pub(crate) fn observe_block_indexed() {
+  update(|metrics| {
+    metrics.blocks_indexed += 1;
+    metrics.last_block_time = Some(ic_cdk::api::time());
+  });
+  save();
+}
+
+pub(crate) fn observe_error(message: String) {
+  update(|metrics| {
+    metrics.last_error = Some(message);
+    metrics.last_error_time = Some(ic_cdk::api::time());
+  });
+}
+
+pub(crate) fn observe_reorg(depth: u32) {
+  update(|metrics| *metrics.reorgs.entry(depth).or_default() += 1);
+  save();
+}
+
+pub(crate) fn observe_rpc(endpoint: &str, ok: bool) {
+  update(|metrics| {
+    *metrics.rpc_calls.entry(endpoint.to_string()).or_default() += 1;
+    if !ok {
+      *metrics
+        .rpc_failures
+        .entry(endpoint.to_string())
+        .or_default() += 1;
+    }
+  });
+}
+
+/// Records the cycles attached to the call that just returned, minus its refund.
+pub(crate) fn observe_outcall_cycles(attached: u128) {
+  let spent = attached.saturating_sub(ic_cdk::api::call::msg_cycles_refunded128());
+  update(|metrics| metrics.outcall_cycles += spent);
+}
+
+pub(crate) fn observe_provider(provider: &str, ok: bool) {
+  update(|metrics| {
+    *metrics
+      .provider_calls
+      .entry(provider.to_string())
+      .or_default() += 1;
+    if !ok {
+      *metrics
+        .provider_failures
+        .entry(provider.to_string())
+        .or_default() += 1;
+    }
+  });
+}
+
+pub(crate) fn observe_provider_disagreement(provider: &str) {
+  update(|metrics| {
+    *metrics
+      .provider_disagreements
+      .entry(provider.to_string())
+      .or_default() += 1;
+  });
+}
+
+pub(crate) fn observe_watchdog_restart() {
+  update(|metrics| metrics.watchdog_restarts += 1);
+}
+
+pub(crate) fn observe_notification_failure() {
+  update(|metrics| metrics.notification_failures += 1);
+}
+
+pub(crate) fn observe_tip_height(tip_height: u32) {
+  update(|metrics| metrics.tip_height = Some(tip_height));
+}
+
+/// Renders the metrics in the Prometheus text exposition format.
+pub fn encode() -> String {
+  let metrics = get();
+  let height = crate::index::mem_latest_block_height();
+
+  let mut out = String::new();
+  if let Some(height) = height {
+    gauge(
+      &mut out,
+      "height",
+      "Height of the latest indexed block.",
+      height,
+    );
+  }
+  if let (Some(height), Some(tip_height)) = (height, metrics.tip_height) {
+    gauge(
+      &mut out,
+      "lag_blocks",
+      "Blocks between the latest indexed block and the Bitcoin tip.",
+      tip_height.saturating_sub(height),
+    );
+  }
+  counter(
+    &mut out,
+    "blocks_indexed_total",
+    "Blocks indexed.",
+    metrics.blocks_indexed,
+  );
+  labeled(
+    &mut out,
+    "reorgs_total",
+    "counter",
+    "Reorgs handled by depth.",
+    "depth",
+    metrics.reorgs.iter(),
+  );
+  labeled(
+    &mut out,
+    "rpc_calls_total",
+    "counter",
+    "RPC calls by endpoint.",
+    "endpoint",
+    metrics.rpc_calls.iter(),
+  );
+  labeled(
+    &mut out,
+    "rpc_failures_total",
+    "counter",
+    "Failed RPC calls by endpoint.",
+    "endpoint",
+    metrics.rpc_failures.iter(),
+  );
+  labeled(
+    &mut out,
+    "provider_calls_total",
+    "counter",
+    "RPC calls by provider.",
+    "provider",
+    metrics.provider_calls.iter(),
+  );
+  labeled(
+    &mut out,
+    "provider_failures_total",
+    "counter",
+    "Failed RPC calls by provider.",
+    "provider",
+    metrics.provider_failures.iter(),
+  );
+  labeled(
+    &mut out,
+    "provider_disagreements_total",
+    "counter",
+    "Results outvoted under the quorum strategy by provider.",
+    "provider",
+    metrics.provider_disagreements.iter(),
+  );
+  counter(
+    &mut out,
+    "outcall_cycles_total",
+    "Cycles spent on outcalls.",
+    metrics.outcall_cycles,
+  );
+  gauge(
+    &mut out,
+    "cycle_balance",
+    "Cycle balance of the canister.",
+    ic_cdk::api::canister_balance128(),
+  );
+  gauge(
+    &mut out,
+    "stable_memory_pages",
+    "Stable memory size in WASM pages.",
+    ic_cdk::api::stable::stable64_size(),
+  );
+  counter(
+    &mut out,
+    "watchdog_restarts_total",
+    "Stalled index loops restarted by the watchdog.",
+    metrics.watchdog_restarts,
+  );
+  counter(
+    &mut out,
+    "notification_failures_total",
+    "Failed subscriber notifications.",
+    metrics.notification_failures,
+  );
+  out
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+  header(out, name, "gauge", help);
+  writeln!(out, "runes_indexer_{name} {value}").unwrap();
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+  header(out, name, "counter", help);
+  writeln!(out, "runes_indexer_{name} {value}").unwrap();
+}
+
+fn labeled<K: std::fmt::Display, V: std::fmt::Display>(
+  out: &mut String,
+  name: &str,
+  kind: &str,
+  help: &str,
+  label: &str,
+  values: impl Iterator<Item = (K, V)>,
+) {
+  header(out, name, kind, help);
+  for (key, value) in values {
+    writeln!(out, "runes_indexer_{name}{{{label}=\"{key}\"}} {value}").unwrap();
+  }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+  writeln!(out, "# HELP runes_indexer_{name} {help}").unwrap();
+  writeln!(out, "# TYPE runes_indexer_{name} {kind}").unwrap();
+}
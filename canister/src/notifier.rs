@@ -28,6 +28,7 @@ pub async fn notify_new_block(
 
   if let Err(e) = ic_cdk::call::<_, ()>(canister_id, "new_block_detected", (req,)).await {
     log!(CRITICAL, "failed to notify new block: {:?}", e);
+    crate::metrics::observe_notification_failure();
   }
   Ok(())
 }
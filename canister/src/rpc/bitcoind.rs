@@ -0,0 +1,115 @@
+use super::*;
+use bitcoincore_rpc_json::GetBlockHeaderResult;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Debug)]
+struct Payload {
+  pub jsonrpc: &'static str,
+  pub id: &'static str,
+  pub method: &'static str,
+  pub params: serde_json::Value,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct Reply<R> {
+  #[allow(dead_code)]
+  pub id: String,
+  pub error: Option<ErrorMsg>,
+  pub result: Option<R>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct ErrorMsg {
+  #[allow(dead_code)]
+  code: i64,
+  message: String,
+}
+
+/// Bitcoin Core JSON-RPC.
+pub(super) struct Bitcoind {
+  pub(super) provider: RpcProvider,
+  pub(super) subnet_nodes: u64,
+}
+
+impl Bitcoind {
+  async fn make_rpc<R>(
+    &self,
+    endpoint: &'static str,
+    params: Vec<serde_json::Value>,
+    max_response_bytes: u64,
+  ) -> Result<R>
+  where
+    R: for<'a> Deserialize<'a> + std::fmt::Debug,
+  {
+    let payload = Payload {
+      jsonrpc: "1.0",
+      id: "btc0",
+      method: endpoint,
+      params: params.into(),
+    };
+    let request = Request {
+      url: self.provider.url.clone(),
+      method: HttpMethod::POST,
+      body: Some(serde_json::to_vec(&payload)?),
+    };
+    let buf = fetch(
+      &self.provider,
+      &request,
+      endpoint,
+      max_response_bytes,
+      self.subnet_nodes,
+    )
+    .await?;
+    let reply: Reply<R> = serde_json::from_slice(&buf)?;
+    if let Some(error) = reply.error {
+      return Err(anyhow!(
+        "rpc error: {:?} => {}",
+        endpoint,
+        self.provider.redact(&error.message)
+      ));
+    }
+    reply
+      .result
+      .ok_or(anyhow!("rpc error: {:?} => {}", endpoint, "No result"))
+  }
+}
+
+impl BlockSource for Bitcoind {
+  async fn get_block(&self, hash: BlockHash) -> Result<Block> {
+    let args = vec![into_json(hash)?, 0.into()];
+    let hex: String = self.make_rpc("getblock", args, MAX_RESPONSE_BYTES).await?;
+    Ok(encode::deserialize_hex(&hex)?)
+  }
+
+  async fn get_raw_transaction(&self, txid: Txid) -> Result<Transaction> {
+    let args = vec![into_json(txid)?, into_json(false)?];
+    let hex: String = self.make_rpc("getrawtransaction", args, 16_384).await?;
+    Ok(encode::deserialize_hex(&hex)?)
+  }
+
+  async fn get_tx_out_proof(&self, txid: Txid) -> Result<MerkleBlock> {
+    let args = vec![into_json([txid])?];
+    let hex: String = self.make_rpc("gettxoutproof", args, 4_096).await?;
+    Ok(encode::deserialize_hex(&hex)?)
+  }
+
+  // 640 ~ 644 bytes
+  async fn get_block_height(&self, hash: BlockHash) -> Result<u32> {
+    let args = vec![into_json(hash)?, true.into()];
+    let res: GetBlockHeaderResult = self.make_rpc("getblockheader", args, 1_024).await?;
+    Ok(u32::try_from(res.height)?)
+  }
+
+  async fn get_block_hash(&self, height: u32) -> Result<BlockHash> {
+    let args = vec![(height as u64).into()];
+    self.make_rpc("getblockhash", args, 256).await
+  }
+}
+
+/// Shorthand for converting a variable into a serde_json::Value.
+fn into_json<T>(val: T) -> Result<serde_json::Value>
+where
+  T: serde::ser::Serialize,
+{
+  Ok(serde_json::to_value(val)?)
+}
@@ -0,0 +1,133 @@
+use super::*;
+use bitcoin::consensus::Decodable;
+use serde::Deserialize;
+
+/// Esplora REST, as served by Blockstream and mempool.space.
+pub(super) struct Esplora {
+  pub(super) provider: RpcProvider,
+  pub(super) subnet_nodes: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct BlockInfo {
+  height: u32,
+}
+
+impl Esplora {
+  /// Fetches `path` under the API root.
+  async fn get(&self, path: &str, max_response_bytes: u64) -> Result<Vec<u8>> {
+    let request = Request {
+      url: format!("{}/{}", self.provider.url.trim_end_matches('/'), path),
+      method: HttpMethod::GET,
+      body: None,
+    };
+    fetch(
+      &self.provider,
+      &request,
+      path,
+      max_response_bytes,
+      self.subnet_nodes,
+    )
+    .await
+  }
+}
+
+/// A response body as text without surrounding whitespace.
+fn text(bytes: Vec<u8>) -> Result<String> {
+  Ok(String::from_utf8(bytes)?.trim().to_string())
+}
+
+/// A consensus encoded value sent as hex text.
+fn parse_hex<T: Decodable>(bytes: Vec<u8>) -> Result<T> {
+  Ok(encode::deserialize_hex(&text(bytes)?)?)
+}
+
+fn parse_block_hash(bytes: Vec<u8>) -> Result<BlockHash> {
+  Ok(text(bytes)?.parse()?)
+}
+
+/// Height from the JSON block summary of `/block/:hash`.
+fn parse_block_height(bytes: &[u8]) -> Result<u32> {
+  let info: BlockInfo = serde_json::from_slice(bytes)?;
+  Ok(info.height)
+}
+
+impl BlockSource for Esplora {
+  async fn get_block(&self, hash: BlockHash) -> Result<Block> {
+    let bytes = self
+      .get(&format!("block/{hash}/raw"), MAX_RESPONSE_BYTES)
+      .await?;
+    Ok(encode::deserialize(&bytes)?)
+  }
+
+  async fn get_raw_transaction(&self, txid: Txid) -> Result<Transaction> {
+    parse_hex(self.get(&format!("tx/{txid}/hex"), 16_384).await?)
+  }
+
+  async fn get_tx_out_proof(&self, txid: Txid) -> Result<MerkleBlock> {
+    parse_hex(
+      self
+        .get(&format!("tx/{txid}/merkleblock-proof"), 4_096)
+        .await?,
+    )
+  }
+
+  async fn get_block_height(&self, hash: BlockHash) -> Result<u32> {
+    parse_block_height(&self.get(&format!("block/{hash}"), 2_048).await?)
+  }
+
+  async fn get_block_hash(&self, height: u32) -> Result<BlockHash> {
+    parse_block_hash(self.get(&format!("block-height/{height}"), 256).await?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn genesis() -> Block {
+    bitcoin::constants::genesis_block(bitcoin::Network::Bitcoin)
+  }
+
+  fn body(text: &str) -> Vec<u8> {
+    text.as_bytes().to_vec()
+  }
+
+  #[test]
+  fn block_hash_text_is_parsed() {
+    let hash = genesis().block_hash();
+    assert_eq!(parse_block_hash(body(&format!("{hash}\n"))).unwrap(), hash);
+    assert!(parse_block_hash(body("Block not found")).is_err());
+    assert!(parse_block_hash(vec![0xff, 0xfe]).is_err());
+  }
+
+  #[test]
+  fn transaction_hex_is_parsed() {
+    let tx = genesis().txdata.remove(0);
+    let hex = encode::serialize_hex(&tx);
+    assert_eq!(parse_hex::<Transaction>(body(&hex)).unwrap(), tx);
+    assert!(parse_hex::<Transaction>(body(&hex[..hex.len() - 2])).is_err());
+    assert!(parse_hex::<Transaction>(body("Transaction not found")).is_err());
+  }
+
+  #[test]
+  fn merkle_proof_hex_is_parsed() {
+    let block = genesis();
+    let txid = block.txdata[0].compute_txid();
+    let proof = MerkleBlock::from_block_with_predicate(&block, |t| *t == txid);
+    let hex = encode::serialize_hex(&proof);
+    assert_eq!(
+      parse_hex::<MerkleBlock>(body(&format!("{hex}\n"))).unwrap(),
+      proof
+    );
+    assert!(parse_hex::<MerkleBlock>(body("")).is_err());
+  }
+
+  #[test]
+  fn block_json_height_is_parsed() {
+    let json = r#"{"id":"0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5","height":840000,"version":710926336,"tx_count":3050}"#;
+    assert_eq!(parse_block_height(json.as_bytes()).unwrap(), 840_000);
+    assert!(parse_block_height(br#"{"id":"00"}"#).is_err());
+    assert!(parse_block_height(b"Block not found").is_err());
+  }
+}
@@ -0,0 +1,358 @@
+//! Hashing of the certified state, shared by the canister and its callers.
+//!
+//! Every certified value is a leaf keyed by a hash. Keys are spread over `2^TREE_DEPTH` buckets
+//! by their first bits, a bucket hashes its leaves in key order, and a binary tree of bucket
+//! hashes yields the tree root. The canister certifies `certified_data(height, block_hash, root)`.
+//! A witness carries the whole bucket of a key plus the sibling hashes on its path, so it proves
+//! both present and absent keys.
+
+use crate::{RuneBalance, RuneEntry, Terms};
+use candid::{CandidType, Deserialize};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+/// Levels of the tree above the buckets.
+pub const TREE_DEPTH: u32 = 20;
+
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct Witness {
+  /// Key and value hash of every leaf in the bucket of the key, ordered by key.
+  pub bucket: Vec<(Hash, Hash)>,
+  /// Sibling hashes from the bucket up to the root.
+  pub siblings: Vec<Hash>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerifyError {
+  /// The response or the witness is malformed.
+  InvalidWitness,
+  /// The witnesses of a response lead to different roots.
+  RootMismatch,
+  /// The certificate does not certify the data the witnesses lead to.
+  CertifiedDataMismatch,
+  InvalidCertificate(String),
+}
+
+pub fn outpoint_key(outpoint: &str) -> Hash {
+  let mut hasher = Sha256::new();
+  hasher.update(b"outpoint\0");
+  hasher.update(outpoint.as_bytes());
+  hasher.finalize().into()
+}
+
+pub fn rune_key(rune_id: &str) -> Hash {
+  let mut hasher = Sha256::new();
+  hasher.update(b"rune\0");
+  hasher.update(rune_id.as_bytes());
+  hasher.finalize().into()
+}
+
+/// Hash of the balances of an outpoint created at `height`, in the order they are returned.
+pub fn rune_balances_hash<S: AsRef<str>>(
+  height: u32,
+  balances: impl IntoIterator<Item = (S, u128)>,
+) -> Hash {
+  let mut hasher = Sha256::new();
+  hasher.update(height.to_le_bytes());
+  for (rune_id, amount) in balances {
+    update_str(&mut hasher, rune_id.as_ref());
+    hasher.update(amount.to_le_bytes());
+  }
+  hasher.finalize().into()
+}
+
+/// Hash of a rune entry. `confirmations` and `outpoints` are left out.
+pub fn rune_entry_hash(entry: &RuneEntry) -> Hash {
+  let mut hasher = Sha256::new();
+  update_str(&mut hasher, &entry.rune_id);
+  hasher.update(entry.block.to_le_bytes());
+  hasher.update(entry.burned.to_le_bytes());
+  hasher.update([entry.divisibility]);
+  update_str(&mut hasher, &entry.etching);
+  hasher.update(entry.mints.to_le_bytes());
+  hasher.update(entry.number.to_le_bytes());
+  hasher.update(entry.premine.to_le_bytes());
+  update_str(&mut hasher, &entry.spaced_rune);
+  update_option(&mut hasher, entry.symbol.as_ref(), |hasher, symbol| {
+    update_str(hasher, symbol)
+  });
+  update_option(&mut hasher, entry.terms.as_ref(), update_terms);
+  hasher.update(entry.timestamp.to_le_bytes());
+  hasher.update([u8::from(entry.turbo)]);
+  hasher.finalize().into()
+}
+
+fn update_terms(hasher: &mut Sha256, terms: &Terms) {
+  update_option(hasher, terms.amount.as_ref(), |hasher, n| {
+    hasher.update(n.to_le_bytes())
+  });
+  update_option(hasher, terms.cap.as_ref(), |hasher, n| {
+    hasher.update(n.to_le_bytes())
+  });
+  for n in [
+    terms.height.0,
+    terms.height.1,
+    terms.offset.0,
+    terms.offset.1,
+  ] {
+    update_option(hasher, n.as_ref(), |hasher, n| {
+      hasher.update(n.to_le_bytes())
+    });
+  }
+}
+
+fn update_str(hasher: &mut Sha256, s: &str) {
+  hasher.update(u32::try_from(s.len()).unwrap().to_le_bytes());
+  hasher.update(s.as_bytes());
+}
+
+fn update_option<T>(hasher: &mut Sha256, value: Option<&T>, f: impl FnOnce(&mut Sha256, &T)) {
+  match value {
+    Some(value) => {
+      hasher.update([1]);
+      f(hasher, value);
+    }
+    None => hasher.update([0]),
+  }
+}
+
+pub fn bucket_index(key: &Hash) -> u32 {
+  u32::from_be_bytes([key[0], key[1], key[2], key[3]]) >> (32 - TREE_DEPTH)
+}
+
+pub fn bucket_hash(leaves: &[(Hash, Hash)]) -> Hash {
+  let mut hasher = Sha256::new();
+  hasher.update(b"bucket\0");
+  for (key, value) in leaves {
+    hasher.update(key);
+    hasher.update(value);
+  }
+  hasher.finalize().into()
+}
+
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+  let mut hasher = Sha256::new();
+  hasher.update(left);
+  hasher.update(right);
+  hasher.finalize().into()
+}
+
+/// Hashes of empty subtrees, indexed by level, `0` being an empty bucket.
+pub fn empty_hashes() -> Vec<Hash> {
+  let mut hashes = vec![bucket_hash(&[])];
+  for level in 0..TREE_DEPTH as usize {
+    hashes.push(node_hash(&hashes[level], &hashes[level]));
+  }
+  hashes
+}
+
+/// The data the canister certifies for the tree root at the given tip.
+pub fn certified_data(height: u32, block_hash: &str, root: &Hash) -> Hash {
+  let mut hasher = Sha256::new();
+  hasher.update(b"runes-indexer\0");
+  hasher.update(height.to_le_bytes());
+  update_str(&mut hasher, block_hash);
+  hasher.update(root);
+  hasher.finalize().into()
+}
+
+/// Returns the tree root the witness leads to if it shows `key` holding `value`, or being absent
+/// when `value` is `None`.
+pub fn witness_root(key: &Hash, value: Option<&Hash>, witness: &Witness) -> Option<Hash> {
+  if witness.siblings.len() != TREE_DEPTH as usize
+    || witness.bucket.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+    || witness
+      .bucket
+      .iter()
+      .any(|(k, _)| bucket_index(k) != bucket_index(key))
+  {
+    return None;
+  }
+
+  let leaf = witness
+    .bucket
+    .iter()
+    .find(|(k, _)| k == key)
+    .map(|(_, v)| v);
+  if leaf != value {
+    return None;
+  }
+
+  let mut index = bucket_index(key);
+  let mut hash = bucket_hash(&witness.bucket);
+  for sibling in &witness.siblings {
+    hash = if index & 1 == 0 {
+      node_hash(&hash, sibling)
+    } else {
+      node_hash(sibling, &hash)
+    };
+    index >>= 1;
+  }
+  Some(hash)
+}
+
+/// Leaf value of the balances returned for an outpoint at tip `height`.
+pub fn rune_balances_value(height: u32, balances: Option<&Vec<RuneBalance>>) -> Option<Hash> {
+  let balances = balances?;
+  let confirmations = balances.first().map_or(0, |balance| balance.confirmations);
+  Some(rune_balances_hash(
+    (height + 1).saturating_sub(confirmations),
+    balances
+      .iter()
+      .map(|balance| (balance.rune_id.as_str(), balance.amount)),
+  ))
+}
+
+/// Checks that all witnesses lead to the same root and returns the data the certificate must
+/// certify.
+pub(crate) fn verify_witnesses<'a>(
+  height: u32,
+  block_hash: &str,
+  leaves: impl IntoIterator<Item = (Hash, Option<Hash>, &'a Witness)>,
+) -> Result<Hash, VerifyError> {
+  let mut root = None;
+  for (key, value, witness) in leaves {
+    let leaf_root =
+      witness_root(&key, value.as_ref(), witness).ok_or(VerifyError::InvalidWitness)?;
+    if *root.get_or_insert(leaf_root) != leaf_root {
+      return Err(VerifyError::RootMismatch);
+    }
+  }
+  let root = root.ok_or(VerifyError::InvalidWitness)?;
+  Ok(certified_data(height, block_hash, &root))
+}
+
+#[cfg(feature = "verify")]
+pub use self::certificate::{verify_certificate, VerifiedCertificate};
+
+#[cfg(feature = "verify")]
+mod certificate {
+  use super::{Hash, VerifyError};
+  use candid::Principal;
+  use ic_certification::{Certificate, HashTree, LookupResult};
+
+  const DER_PREFIX: [u8; 37] = [
+    0x30, 0x81, 0x82, 0x30, 0x1d, 0x06, 0x0d, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05,
+    0x03, 0x01, 0x02, 0x01, 0x06, 0x0c, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03,
+    0x02, 0x01, 0x03, 0x61, 0x00,
+  ];
+
+  #[derive(Debug, PartialEq, Eq)]
+  pub struct VerifiedCertificate {
+    pub certified_data: Hash,
+    /// Time of the certificate in nanoseconds since the epoch.
+    pub time: u64,
+  }
+
+  /// Verifies a certificate returned by the canister against the DER encoded IC root key and
+  /// returns the data it certifies for `canister_id`.
+  pub fn verify_certificate(
+    certificate: &[u8],
+    canister_id: Principal,
+    root_key: &[u8],
+  ) -> Result<VerifiedCertificate, VerifyError> {
+    let certificate = decode(certificate)?;
+
+    let key = match &certificate.delegation {
+      Some(delegation) => {
+        let delegated = decode(&delegation.certificate)?;
+        if delegated.delegation.is_some() {
+          return Err(invalid("nested delegation"));
+        }
+        verify_signature(&delegated, root_key)?;
+
+        let ranges = lookup(
+          &delegated.tree,
+          [
+            b"subnet".as_ref(),
+            &delegation.subnet_id,
+            b"canister_ranges".as_ref(),
+          ],
+        )?;
+        let ranges: Vec<(serde_bytes::ByteBuf, serde_bytes::ByteBuf)> =
+          serde_cbor::from_slice(ranges).map_err(invalid)?;
+        let id = canister_id.as_slice();
+        if !ranges
+          .iter()
+          .any(|(low, high)| low.as_slice() <= id && id <= high.as_slice())
+        {
+          return Err(invalid("canister outside of the delegated ranges"));
+        }
+
+        lookup(
+          &delegated.tree,
+          [
+            b"subnet".as_ref(),
+            &delegation.subnet_id,
+            b"public_key".as_ref(),
+          ],
+        )?
+        .to_vec()
+      }
+      None => root_key.to_vec(),
+    };
+    verify_signature(&certificate, &key)?;
+
+    let certified_data = lookup(
+      &certificate.tree,
+      [
+        b"canister".as_ref(),
+        canister_id.as_slice(),
+        b"certified_data".as_ref(),
+      ],
+    )?
+    .try_into()
+    .map_err(|_| invalid("certified data is not a hash"))?;
+    let time = leb128(lookup(&certificate.tree, [b"time".as_ref()])?)?;
+
+    Ok(VerifiedCertificate {
+      certified_data,
+      time,
+    })
+  }
+
+  fn decode(bytes: &[u8]) -> Result<Certificate, VerifyError> {
+    serde_cbor::from_slice(bytes).map_err(invalid)
+  }
+
+  fn verify_signature(certificate: &Certificate, der_key: &[u8]) -> Result<(), VerifyError> {
+    let key = der_key
+      .strip_prefix(DER_PREFIX.as_ref())
+      .filter(|key| key.len() == 96)
+      .ok_or_else(|| invalid("malformed public key"))?;
+    let mut message = b"\x0Dic-state-root".to_vec();
+    message.extend_from_slice(&certificate.tree.digest());
+    ic_verify_bls_signature::verify_bls_signature(&certificate.signature, &message, key)
+      .map_err(|_| invalid("invalid signature"))
+  }
+
+  fn lookup<'a, const N: usize>(
+    tree: &'a HashTree,
+    path: [&[u8]; N],
+  ) -> Result<&'a [u8], VerifyError> {
+    match tree.lookup_path(path) {
+      LookupResult::Found(value) => Ok(value),
+      _ => Err(invalid("path not found")),
+    }
+  }
+
+  fn leb128(bytes: &[u8]) -> Result<u64, VerifyError> {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().enumerate() {
+      let shift = 7 * u32::try_from(i).unwrap();
+      if shift >= 64 {
+        return Err(invalid("time overflows"));
+      }
+      value |= u64::from(byte & 0x7f) << shift;
+      if byte & 0x80 == 0 {
+        return Ok(value);
+      }
+    }
+    Err(invalid("truncated time"))
+  }
+
+  fn invalid(e: impl std::fmt::Display) -> VerifyError {
+    VerifyError::InvalidCertificate(e.to_string())
+  }
+}
@@ -1,4 +1,7 @@
+pub mod certification;
+
 use candid::{CandidType, Deserialize};
+use certification::{Hash, VerifyError, Witness};
 
 #[derive(Debug, CandidType, Deserialize)]
 pub struct RuneBalance {
@@ -9,6 +12,25 @@ pub struct RuneBalance {
   pub symbol: Option<String>,
 }
 
+#[derive(Debug, CandidType, Deserialize)]
+pub struct RuneAmount {
+  pub rune_id: String,
+  pub amount: u128,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct OutPointRuneBalances {
+  pub outpoint: String,
+  pub balances: Vec<RuneBalance>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct AddressRuneBalances {
+  pub outpoints: Vec<OutPointRuneBalances>,
+  pub totals: Vec<RuneAmount>,
+  pub next_cursor: Option<String>,
+}
+
 #[derive(Debug, CandidType, Deserialize)]
 pub struct GetEtchingResult {
   pub confirmations: u32,
@@ -23,6 +45,131 @@ pub struct Terms {
   pub offset: (Option<u64>, Option<u64>),
 }
 
+#[derive(Debug, CandidType, Deserialize, PartialEq, Eq)]
+pub enum MintError {
+  Cap(u128),
+  End(u64),
+  Start(u64),
+  Unmintable,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct MintStatus {
+  pub rune_id: String,
+  /// Height of the next block, the one `mintable` and `error` refer to.
+  pub height: u64,
+  pub mintable: bool,
+  pub error: Option<MintError>,
+  pub amount: Option<u128>,
+  pub mints: u128,
+  pub cap: Option<u128>,
+  pub remaining: u128,
+  pub start: Option<u64>,
+  pub end: Option<u64>,
+  pub supply: u128,
+  pub max_supply: u128,
+}
+
+/// Health of the indexer, timestamps are in nanoseconds since the epoch.
+#[derive(Debug, CandidType, Deserialize)]
+pub struct IndexerStatus {
+  pub height: Option<u32>,
+  pub block_hash: Option<String>,
+  /// Tip height last reported by the Bitcoin API.
+  pub bitcoin_tip_height: Option<u32>,
+  /// Blocks between the latest indexed block and the Bitcoin tip.
+  pub lag: Option<u32>,
+  /// Whether the index timer loop is scheduled.
+  pub running: bool,
+  pub shutting_down: bool,
+  /// Whether an iteration of the loop is indexing a block, it finishes even after `stop`.
+  pub in_flight: bool,
+  /// Whether the loop stopped on an error it cannot recover from and waits for `start`.
+  pub halted: bool,
+  /// Whether indices added by an upgrade are still being populated, indexing waits for it.
+  pub backfilling: bool,
+  /// Height from which the address index covers every output, `0` unless the deployment
+  /// predates it.
+  pub address_index_start: u32,
+  pub last_error: Option<String>,
+  pub last_error_time: Option<u64>,
+  pub last_block_time: Option<u64>,
+  /// Nanoseconds since the last successfully indexed block.
+  pub since_last_block: Option<u64>,
+}
+
+/// Sizes of the index, the counters are numbers of entries in the named map.
+#[derive(Debug, CandidType, Deserialize)]
+pub struct IndexStats {
+  pub latest_height: Option<u32>,
+  pub latest_block_hash: Option<String>,
+  pub runes: u64,
+  pub reserved_runes: u64,
+  pub rune_to_rune_id: u64,
+  pub rune_id_to_rune_entry: u64,
+  pub transaction_id_to_rune: u64,
+  pub outpoint_to_rune_balances: u64,
+  pub outpoint_to_height: u64,
+  pub script_hash_to_outpoints: u64,
+  pub script_hash_to_rune_totals: u64,
+  pub outpoint_to_script_hash: u64,
+  pub rune_id_to_outpoints: u64,
+  pub number_to_rune_id: u64,
+  pub rune_transactions: u64,
+  pub spent_outpoints: u64,
+  pub supply_changes: u64,
+  pub block_runes_summaries: u64,
+  pub certified_leaves: u64,
+  /// Change records retained for reorg rollback.
+  pub change_records: u64,
+  /// Stable memory in bytes used by each `MemoryId`.
+  pub memory_sizes: Vec<u64>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct RuneMints {
+  pub rune_id: String,
+  pub mints: u128,
+  /// Amount minted, `mints` times the amount per mint.
+  pub amount: u128,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct BlockRunesSummary {
+  pub height: u32,
+  pub block_hash: String,
+  pub timestamp: u64,
+  /// IDs of the runes etched in the block.
+  pub etched: Vec<String>,
+  pub mints: Vec<RuneMints>,
+  pub burned: Vec<RuneAmount>,
+  /// Number of transactions that spent, created or burned runes.
+  pub transactions: u32,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct OutPointSpender {
+  pub outpoint: String,
+  /// Height the outpoint was created at.
+  pub height: u32,
+  pub spender: String,
+  pub spent_height: u32,
+  /// Confirmations of the spending transaction.
+  pub confirmations: u32,
+  pub rune_balances: Vec<RuneAmount>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct RuneSupply {
+  pub rune_id: String,
+  pub height: u32,
+  pub premine: u128,
+  pub mints: u128,
+  pub burned: u128,
+  /// Premine plus minted amount at the end of block `height`, burned runes included.
+  pub supply: u128,
+}
+
 #[derive(Debug, CandidType, Deserialize)]
 pub struct RuneEntry {
   pub confirmations: u32,
@@ -33,6 +180,7 @@ pub struct RuneEntry {
   pub etching: String,
   pub mints: u128,
   pub number: u64,
+  pub outpoints: u64,
   pub premine: u128,
   pub spaced_rune: String,
   pub symbol: Option<String>,
@@ -41,7 +189,225 @@ pub struct RuneEntry {
   pub turbo: bool,
 }
 
+#[derive(Debug, CandidType, Deserialize)]
+pub enum RunesOrder {
+  Number,
+  Block,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct GetRunesResult {
+  pub runes: Vec<RuneEntry>,
+  pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct RuneHolder {
+  pub outpoint: String,
+  pub amount: u128,
+  pub height: u32,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct RuneHolders {
+  pub holders: Vec<RuneHolder>,
+  pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct Edict {
+  pub rune_id: String,
+  pub amount: u128,
+  pub output: u32,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct Etching {
+  pub spaced_rune: Option<String>,
+  pub divisibility: Option<u8>,
+  pub premine: Option<u128>,
+  pub symbol: Option<String>,
+  pub terms: Option<Terms>,
+  pub turbo: bool,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct Runestone {
+  pub edicts: Vec<Edict>,
+  pub etching: Option<Etching>,
+  pub mint: Option<String>,
+  pub pointer: Option<u32>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct Cenotaph {
+  pub etching: Option<String>,
+  pub flaw: Option<String>,
+  pub mint: Option<String>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub enum Artifact {
+  Runestone(Runestone),
+  Cenotaph(Cenotaph),
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct OutputRuneAmounts {
+  pub vout: u32,
+  pub amounts: Vec<RuneAmount>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct OutPointRuneAmounts {
+  pub outpoint: String,
+  pub amounts: Vec<RuneAmount>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct GetRunestoneResult {
+  pub confirmations: u32,
+  pub height: u32,
+  pub tx_index: u32,
+  pub artifact: Option<Artifact>,
+  pub inputs: Vec<OutPointRuneAmounts>,
+  pub minted: Option<RuneAmount>,
+  pub etched: Option<String>,
+  pub pointer: Option<u32>,
+  pub allocations: Vec<OutputRuneAmounts>,
+  pub burned: Vec<RuneAmount>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct SimulateTransactionResult {
+  pub artifact: Option<Artifact>,
+  pub inputs: Vec<OutPointRuneAmounts>,
+  pub minted: Option<RuneAmount>,
+  pub mint_error: Option<String>,
+  pub etched: Option<String>,
+  pub etching_error: Option<String>,
+  pub pointer: Option<u32>,
+  pub allocations: Vec<OutputRuneAmounts>,
+  pub burned: Vec<RuneAmount>,
+}
+
 #[derive(Debug, CandidType, Deserialize)]
 pub enum Error {
   MaxOutpointsExceeded,
+  InvalidAddress,
+  InvalidCursor,
+  InvalidRuneId,
+  MaxRunesExceeded,
+  InvalidTransaction,
+  HistoryDisabled,
+  HeightNotAvailable,
+  InvalidOutPoint,
+  CertificateUnavailable,
+  /// An index added by an upgrade is still being populated.
+  Backfilling,
+  /// A balance refers to a rune the index has no entry for.
+  RuneNotFound,
+  /// The address index misses outputs indexed before it was introduced, until a re-index.
+  AddressIndexIncomplete,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub struct CertifiedOutPointBalances {
+  pub outpoint: String,
+  pub balances: Option<Vec<RuneBalance>>,
+  pub witness: Witness,
+}
+
+/// Balances of outputs with a certificate and witnesses proving them at tip `height`.
+#[derive(Debug, CandidType, Deserialize)]
+pub struct CertifiedRuneBalances {
+  pub certificate: Vec<u8>,
+  pub height: u32,
+  pub block_hash: String,
+  pub outputs: Vec<CertifiedOutPointBalances>,
+}
+
+impl CertifiedRuneBalances {
+  /// Checks the witnesses and returns the data the certificate has to certify.
+  pub fn certified_data(&self) -> Result<Hash, VerifyError> {
+    certification::verify_witnesses(
+      self.height,
+      &self.block_hash,
+      self.outputs.iter().map(|output| {
+        (
+          certification::outpoint_key(&output.outpoint),
+          certification::rune_balances_value(self.height, output.balances.as_ref()),
+          &output.witness,
+        )
+      }),
+    )
+  }
+
+  #[cfg(feature = "verify")]
+  pub fn verify(
+    &self,
+    canister_id: candid::Principal,
+    root_key: &[u8],
+  ) -> Result<certification::VerifiedCertificate, VerifyError> {
+    verify(
+      &self.certificate,
+      self.certified_data()?,
+      canister_id,
+      root_key,
+    )
+  }
+}
+
+/// A rune entry with a certificate and a witness proving it at tip `height`.
+#[derive(Debug, CandidType, Deserialize)]
+pub struct CertifiedRuneEntry {
+  pub certificate: Vec<u8>,
+  pub height: u32,
+  pub block_hash: String,
+  pub rune_id: String,
+  pub entry: Option<RuneEntry>,
+  pub witness: Witness,
+}
+
+impl CertifiedRuneEntry {
+  /// Checks the witness and returns the data the certificate has to certify.
+  pub fn certified_data(&self) -> Result<Hash, VerifyError> {
+    certification::verify_witnesses(
+      self.height,
+      &self.block_hash,
+      [(
+        certification::rune_key(&self.rune_id),
+        self.entry.as_ref().map(certification::rune_entry_hash),
+        &self.witness,
+      )],
+    )
+  }
+
+  #[cfg(feature = "verify")]
+  pub fn verify(
+    &self,
+    canister_id: candid::Principal,
+    root_key: &[u8],
+  ) -> Result<certification::VerifiedCertificate, VerifyError> {
+    verify(
+      &self.certificate,
+      self.certified_data()?,
+      canister_id,
+      root_key,
+    )
+  }
+}
+
+#[cfg(feature = "verify")]
+fn verify(
+  certificate: &[u8],
+  certified_data: Hash,
+  canister_id: candid::Principal,
+  root_key: &[u8],
+) -> Result<certification::VerifiedCertificate, VerifyError> {
+  let certificate = certification::verify_certificate(certificate, canister_id, root_key)?;
+  if certificate.certified_data != certified_data {
+    return Err(VerifyError::CertifiedDataMismatch);
+  }
+  Ok(certificate)
 }